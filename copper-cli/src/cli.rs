@@ -1,19 +1,25 @@
 use std::path::PathBuf;
+use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use clap::StructOpt;
+use copper::downloader::{Downloader, Mirrors};
+use copper::launcher::{AuthenticationDetails, JavaVersionCheck};
+use copper::util::{ClientConfig, ProxyConfig, ProxyCredentials};
 
 use crate::{
     download_deps::{download_deps, VersionId},
-    launch_minecraft::launch_minecraft,
+    launch_minecraft::{launch_minecraft, LaunchOptions},
 };
 
-pub async fn handle_args(args: Args) -> Result<()> {
-    match args {
+pub async fn handle_args(cli: Cli) -> Result<()> {
+    let downloader = cli.network.downloader()?;
+
+    match cli.command {
         Args::DownloadDependencies {
             root,
             version: version_id,
-        } => download_deps(root, version_id).await?,
+        } => download_deps(root, version_id, downloader).await?,
         Args::Launch {
             root,
             version: version_id,
@@ -21,11 +27,132 @@ pub async fn handle_args(args: Args) -> Result<()> {
             username,
             uuid,
             xbox_uid,
-        } => launch_minecraft(username, uuid, access_token, xbox_uid, root, version_id).await?,
+            offline,
+            natives_directory,
+            no_log4j_mitigation,
+            ignore_java_version,
+        } => {
+            let authentication_details = AuthenticationDetails {
+                username,
+                uuid,
+                access_token,
+                xbox_uid,
+                client_id: None,
+                is_demo_user: false,
+            };
+
+            launch_minecraft(
+                authentication_details,
+                root,
+                version_id,
+                LaunchOptions {
+                    offline,
+                    natives_directory,
+                    skip_log4j_mitigation: no_log4j_mitigation,
+                    java_version_check: if ignore_java_version {
+                        JavaVersionCheck::Warn
+                    } else {
+                        JavaVersionCheck::Refuse
+                    },
+                },
+                downloader,
+            )
+            .await?
+        }
     }
     Ok(())
 }
 
+#[derive(Debug, StructOpt)]
+pub struct Cli {
+    #[structopt(flatten)]
+    pub network: NetworkOptions,
+    #[structopt(subcommand)]
+    pub command: Args,
+}
+
+/// How everything is downloaded, shared by all commands
+#[derive(Debug, StructOpt)]
+pub struct NetworkOptions {
+    /// The maximum amount of files to download at once.
+    #[structopt(long, global = true, value_parser, default_value_t = Downloader::DEFAULT_MAX_CONCURRENT_DOWNLOADS)]
+    max_concurrent_downloads: usize,
+    /// Download from a mirror instead of the given host, e.g.
+    /// `resources.download.minecraft.net=https://cache.example.com/assets`.
+    ///
+    /// If the mirror fails, the official host is used. Can be given multiple times
+    #[structopt(long = "mirror", global = true, value_parser = parse_mirror)]
+    mirrors: Vec<(String, String)>,
+    /// Download everything through the BMCLAPI mirror
+    #[structopt(long, global = true, value_parser)]
+    bmclapi: bool,
+    /// Send every request through this proxy, e.g. `http://proxy.example.com:8080` or
+    /// `socks5://127.0.0.1:1080`
+    #[structopt(long, global = true, value_parser)]
+    proxy: Option<String>,
+    /// The username to authenticate to the proxy with
+    #[structopt(long, global = true, value_parser, requires = "proxy")]
+    proxy_user: Option<String>,
+    /// The password to authenticate to the proxy with
+    #[structopt(long, global = true, value_parser, requires = "proxy-user")]
+    proxy_password: Option<String>,
+    /// A PEM file with extra root certificates to trust. Can be given multiple times
+    #[structopt(long, global = true, value_parser)]
+    ca_cert: Vec<PathBuf>,
+    /// How many seconds to wait for a connection to be made
+    #[structopt(long, global = true, value_parser)]
+    connect_timeout: Option<u64>,
+    /// How many seconds a download can go without receiving anything before it fails
+    #[structopt(long, global = true, value_parser)]
+    read_timeout: Option<u64>,
+    /// The user agent to send with every request
+    #[structopt(long, global = true, value_parser)]
+    user_agent: Option<String>,
+}
+
+impl NetworkOptions {
+    /// Creates the downloader every request goes through
+    pub fn downloader(self) -> Result<Downloader> {
+        let root_certificates = self
+            .ca_cert
+            .iter()
+            .map(|path| {
+                std::fs::read(path)
+                    .map_err(|err| anyhow!("Failed to read {}: {}", path.display(), err))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let config = ClientConfig {
+            proxy: self.proxy.map(|url| ProxyConfig {
+                url,
+                credentials: self.proxy_user.map(|username| ProxyCredentials {
+                    username,
+                    password: self.proxy_password.unwrap_or_default(),
+                }),
+            }),
+            root_certificates,
+            connect_timeout: self.connect_timeout.map(Duration::from_secs),
+            read_timeout: self.read_timeout.map(Duration::from_secs),
+            user_agent: self.user_agent,
+        };
+
+        let mirrors = self.mirrors.into_iter().fold(
+            if self.bmclapi {
+                Mirrors::bmclapi()
+            } else {
+                Mirrors::default()
+            },
+            |mirrors, (host, mirror)| mirrors.with_mirror(host, mirror),
+        );
+
+        Ok(
+            Downloader::from_config(&config, self.max_concurrent_downloads)
+                .map_err(|err| anyhow!("Failed to create the HTTP client: {}", err))?
+                .with_mirrors(mirrors),
+        )
+    }
+}
+
 #[derive(Debug, StructOpt)]
 pub enum Args {
     /// Download minecrafts dependencies.
@@ -66,5 +193,32 @@ pub enum Args {
 
         #[structopt(short, long, value_parser)]
         xbox_uid: String,
+
+        /// Launch without making any network requests.
+        ///
+        /// Everything has to be downloaded already, and the version can't be "latest"
+        #[structopt(long, value_parser)]
+        offline: bool,
+
+        /// Where to extract the natives to. Defaults to `versions/<version>/natives`
+        #[structopt(long, value_parser)]
+        natives_directory: Option<PathBuf>,
+
+        /// Don't protect versions with a vulnerable log4j (1.7 to 1.18.0) from Log4Shell.
+        ///
+        /// Only use this if the game is already protected some other way
+        #[structopt(long, value_parser)]
+        no_log4j_mitigation: bool,
+
+        /// Launch even if the java is too old for the version, only warning about it
+        #[structopt(long, value_parser)]
+        ignore_java_version: bool,
     },
 }
+
+fn parse_mirror(mirror: &str) -> Result<(String, String), String> {
+    mirror
+        .split_once('=')
+        .map(|(host, url)| (host.to_string(), url.to_string()))
+        .ok_or_else(|| format!("expected HOST=URL, got {}", mirror))
+}
@@ -1,14 +1,18 @@
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use indicatif::{HumanBytes, HumanDuration, MultiProgress, ProgressBar, ProgressStyle};
 use std::convert::Infallible;
 use std::path::PathBuf;
 use std::str::FromStr;
 use tokio::{fs, task::JoinHandle};
-use tracing::info;
+use tracing::{error, info, warn};
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use copper::assets::structs::launcher_meta::LauncherMeta;
 use copper::assets::structs::version::Version as VersionManifest;
-use copper::util::{create_client, DivPathBuf};
+use copper::downloader::{DownloadStatus, DownloadSummary, Downloader};
+use copper::java::runtime::JavaRuntimeManager;
+use copper::log4j::Log4jMitigation;
+use copper::resolver::VersionResolver;
+use copper::util::{DivPathBuf, DownloadProgress};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum VersionId {
@@ -20,13 +24,9 @@ impl FromStr for VersionId {
     type Err = Infallible;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let path_buf = PathBuf::from_str(s);
-        if let Ok(pb) = path_buf {
-            if pb.exists() {
-                Ok(Self::Path(pb))
-            } else {
-                Ok(Self::Id(s.to_string()))
-            }
+        let Ok(pb) = PathBuf::from_str(s);
+        if pb.exists() {
+            Ok(Self::Path(pb))
         } else {
             Ok(Self::Id(s.to_string()))
         }
@@ -34,73 +34,76 @@ impl FromStr for VersionId {
 }
 
 #[tracing::instrument]
-pub async fn download_deps(root: String, version_id: VersionId) -> anyhow::Result<()> {
-    let launcher_meta = LauncherMeta::download_meta()
-        .await
-        .map_err(|err| anyhow!("Failed to download launcher meta: {}", err))?;
-
-    let version = match version_id {
+pub async fn download_deps(
+    root: String,
+    version_id: VersionId,
+    downloader: Downloader,
+) -> anyhow::Result<()> {
+    let resolver = VersionResolver::new(PathBuf::from(&root).join("versions"));
+
+    let (mut version, replaced_libraries) = match version_id {
         VersionId::Id(id) => {
-            let version_info = if id == "latest" {
-                launcher_meta
+            let id = if id == "latest" {
+                LauncherMeta::download_meta(&downloader)
+                    .await
+                    .map_err(|err| anyhow!("Failed to download launcher meta: {}", err))?
                     .latest
-                    .version_for_release(&launcher_meta)
-                    .clone()
+                    .release
             } else {
-                launcher_meta
-                    .versions
-                    .iter()
-                    .find(|version| version.id == id)
-                    .ok_or(anyhow!("Version {} not found", id))?
-                    .clone()
+                id
             };
 
-            version_info.version().await.map_err(|err| {
-                anyhow!(
-                    "Failed to download version manifest for version {}: {}",
-                    &version_info.id,
-                    err
-                )
-            })?
+            resolver
+                .resolve_with_replaced(&id, &downloader)
+                .await
+                .map_err(|err| {
+                    anyhow!(
+                        "Failed to resolve version manifest for version {}: {}",
+                        id,
+                        err
+                    )
+                })?
         }
         VersionId::Path(path) => {
-            let file = fs::read_to_string(path).await?;
+            let file = fs::read_to_string(&path).await?;
             let new_json = serde_json::from_str::<VersionManifest>(&file)?;
-            if let Some(other) = new_json.inherits_from.clone() {
-                new_json.merge(
-                    launcher_meta
-                        .versions
-                        .iter()
-                        .find(|version| version.id == other)
-                        .ok_or(anyhow!("Version {} not found", other))?
-                        .clone()
-                        .version()
-                        .await
-                        .map_err(|err| {
-                            anyhow!(
-                                "Failed to download version manifest for version {}: {}",
-                                other,
-                                err
-                            )
-                        })?,
-                )
-            } else {
-                new_json
-            }
+
+            resolver
+                .resolve_version_with_replaced(new_json, &downloader)
+                .await
+                .map_err(|err| {
+                    anyhow!(
+                        "Failed to resolve version manifest {}: {}",
+                        path.display(),
+                        err
+                    )
+                })?
         }
     };
 
+    for library in &replaced_libraries {
+        info!(
+            "Using library {} instead of {} from a parent version",
+            library.kept, library.replaced
+        );
+    }
+
+    version
+        .resolve_library_downloads(&downloader)
+        .await
+        .map_err(|err| anyhow!("Failed to resolve library downloads: {}", err))?;
+
     let id = version.id.as_ref().ok_or(anyhow!("Version id not found"))?;
 
     info!("Downloaded version manifest for version {}", &id);
 
     let root_path = DivPathBuf(PathBuf::from(root));
     let libraries_path = &root_path / "libraries";
-    let version_path = &root_path / "versions" / &id;
+    let version_path = &root_path / "versions" / id;
 
     let bars = MultiProgress::new();
     let style = ProgressStyle::default_bar()
-        .template("[{elapsed_precise}] [{bar:40.green/cyan}] {pos:>7}/{len:7} {msg}");
+        .template("[{elapsed_precise}] [{bar:40.green/cyan}] {bytes:>10}/{total_bytes:10} {msg}");
 
     let libraries_bar = bars.add(ProgressBar::new(1000));
     let assets_bar = bars.add(ProgressBar::new(1000));
@@ -112,11 +115,11 @@ pub async fn download_deps(root: String, version_id: VersionId) -> anyhow::Resul
     assets_bar.set_message("Downloading assets");
 
     let mut libraries_watcher = version
-        .start_download_libraries(libraries_path.to_path_buf(), create_client())
+        .start_download_libraries(libraries_path.to_path_buf(), &downloader)
         .await
         .map_err(|err| anyhow!("Failed to download libraries: {}", err))?;
 
-    let asset_index = version.asset_index().await.map_err(|err| {
+    let asset_index = version.asset_index(&downloader).await.map_err(|err| {
         anyhow!(
             "Failed to download asset index for version {}: {}",
             &id,
@@ -125,52 +128,81 @@ pub async fn download_deps(root: String, version_id: VersionId) -> anyhow::Resul
     })?;
 
     let mut asset_watcher = asset_index
-        .start_download_assets((&root_path / "assets" / "objects").to_path_buf())
+        .start_download_assets(
+            (&root_path / "assets" / "objects").to_path_buf(),
+            &downloader,
+        )
         .await;
 
+    let cancellation_tokens = [
+        libraries_watcher.cancellation_token.clone(),
+        asset_watcher.cancellation_token.clone(),
+    ];
+
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            info!("Cancelling downloads");
+            cancellation_tokens.iter().for_each(|token| token.cancel());
+        }
+    });
+
     libraries_bar.enable_steady_tick(100);
     assets_bar.enable_steady_tick(100);
 
-    let libraries: JoinHandle<Result<(), ()>> = tokio::spawn(async move {
-        while let Ok(_) = libraries_watcher.progress_watcher.changed().await {
+    let libraries: JoinHandle<Result<DownloadSummary, ()>> = tokio::spawn(async move {
+        while libraries_watcher.progress_watcher.changed().await.is_ok() {
             let progress = *libraries_watcher.progress_watcher.borrow();
-            libraries_bar.clone().set_length(progress.total_size);
-            libraries_bar.clone().set_position(progress.finished);
+            libraries_bar.clone().set_length(progress.total_bytes);
+            libraries_bar
+                .clone()
+                .set_position(progress.downloaded_bytes);
+            libraries_bar
+                .clone()
+                .set_message(progress_message("libraries", &progress));
         }
 
-        libraries_watcher.download_task.await.map_err(|_err| ())?;
+        let summary = libraries_watcher.download_task.await.map_err(|_err| ())?;
         libraries_bar
             .clone()
-            .finish_with_message("Done downloading libraries!");
+            .finish_with_message(finished_message("libraries", &summary));
 
-        Ok(())
+        Ok(summary)
     });
 
-    let assets: JoinHandle<Result<(), ()>> = tokio::spawn(async move {
-        while let Ok(_) = asset_watcher.progress_watcher.changed().await {
+    let assets: JoinHandle<Result<DownloadSummary, ()>> = tokio::spawn(async move {
+        while asset_watcher.progress_watcher.changed().await.is_ok() {
             let progress = *asset_watcher.progress_watcher.borrow();
-            assets_bar.clone().set_length(progress.total_size);
-            assets_bar.clone().set_position(progress.finished);
+            assets_bar.clone().set_length(progress.total_bytes);
+            assets_bar.clone().set_position(progress.downloaded_bytes);
+            assets_bar
+                .clone()
+                .set_message(progress_message("assets", &progress));
         }
 
-        asset_watcher.download_task.await.map_err(|_err| ())?;
+        let summary = asset_watcher.download_task.await.map_err(|_err| ())?;
         assets_bar
             .clone()
-            .finish_with_message("Done downloading assets!");
+            .finish_with_message(finished_message("assets", &summary));
 
-        Ok(())
+        Ok(summary)
     });
 
     bars.join()?;
 
-    libraries
+    let libraries_summary = libraries
         .await?
         .map_err(|_err| anyhow!("Failed to download libraries"))?;
 
-    assets
+    let assets_summary = assets
         .await?
         .map_err(|_err| anyhow!("Failed to download assets"))?;
 
+    if libraries_summary.status == DownloadStatus::Cancelled
+        || assets_summary.status == DownloadStatus::Cancelled
+    {
+        bail!("Downloads were cancelled, run this again to continue");
+    }
+
     asset_index
         .save_index((&root_path / "assets" / "indexes" / &format!("{}.json", id)).to_path_buf())
         .await
@@ -191,11 +223,98 @@ pub async fn download_deps(root: String, version_id: VersionId) -> anyhow::Resul
     info!("Saved the version manifest");
 
     version
-        .download_client_jar((&version_path / &format!("{}.jar", &id)).to_path_buf())
+        .download_client_jar(
+            (&version_path / &format!("{}.jar", &id)).to_path_buf(),
+            &downloader,
+        )
         .await
         .map_err(|err| anyhow!("Failed to download client jar for version {}: {}", &id, err))?;
 
     info!("Saved the client jar");
 
+    version
+        .download_logging_config((&root_path / "assets").to_path_buf(), &downloader)
+        .await
+        .map_err(|err| {
+            anyhow!(
+                "Failed to download logging config for version {}: {}",
+                &id,
+                err
+            )
+        })?;
+
+    info!("Saved the logging config");
+
+    if let Some(Log4jMitigation::PatchedConfig(config)) = Log4jMitigation::for_version(&version) {
+        config
+            .download(&(&root_path / "assets"), &downloader)
+            .await
+            .map_err(|err| anyhow!("Failed to download patched log4j config: {}", err))?;
+
+        info!("Saved the patched log4j config");
+    }
+
+    match JavaRuntimeManager::new((&root_path / "runtime").to_path_buf())
+        .install_for_version(&version, &downloader)
+        .await
+    {
+        Ok(java_path) => info!("Installed java to {}", java_path.display()),
+        Err(err) => warn!(
+            "Failed to install java for version {}, a system java will be used: {}",
+            &id, err
+        ),
+    }
+
+    let failed =
+        report_failures("library", &libraries_summary) + report_failures("asset", &assets_summary);
+
+    if failed > 0 {
+        bail!(
+            "{} files failed to download, run this again to retry them",
+            failed
+        );
+    }
+
     Ok(())
 }
+
+fn finished_message(name: &str, summary: &DownloadSummary) -> String {
+    if summary.status == DownloadStatus::Cancelled {
+        format!("Cancelled downloading {}", name)
+    } else if summary.is_success() {
+        format!("Done downloading {}!", name)
+    } else {
+        format!("Failed to download {} {}", summary.failed.len(), name)
+    }
+}
+
+/// Logs every failed download, returning how many there were
+fn report_failures(kind: &str, summary: &DownloadSummary) -> usize {
+    for failed in &summary.failed {
+        error!(
+            "Failed to download {} {} to {}: {}",
+            kind,
+            failed.job.url,
+            failed.job.path.display(),
+            failed.error
+        );
+    }
+
+    summary.failed.len()
+}
+
+fn progress_message(name: &str, progress: &DownloadProgress) -> String {
+    let eta = progress
+        .eta()
+        .map(|eta| format!(", {} left", HumanDuration(eta)))
+        .unwrap_or_default();
+
+    format!(
+        "Downloading {} ({}/{} files, {}/s{})",
+        name,
+        progress.finished,
+        progress.total_size,
+        HumanBytes(progress.bytes_per_second),
+        eta
+    )
+}
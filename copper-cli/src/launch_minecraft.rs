@@ -1,35 +1,45 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use tokio::fs;
 use tokio::io::AsyncBufReadExt;
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use copper::{
-    assets::structs::launcher_meta::LauncherMeta,
-    launcher::{AuthenticationDetails, Launcher, RamSize},
-    util::create_client,
+    assets::structs::{launcher_meta::LauncherMeta, version::Version},
+    downloader::Downloader,
+    java::{
+        discovery::{discover, find_java},
+        runtime::{JavaRuntimeManager, DEFAULT_COMPONENT},
+    },
+    launcher::{AuthenticationDetails, JavaVersionCheck, Launcher, RamSize},
+    resolver::VersionResolver,
 };
 use tracing::{info, warn};
 
+/// The flags of the launch command that change how the game is launched
+#[derive(Debug)]
+pub struct LaunchOptions {
+    pub offline: bool,
+    pub natives_directory: Option<PathBuf>,
+    pub skip_log4j_mitigation: bool,
+    pub java_version_check: JavaVersionCheck,
+}
+
 #[tracing::instrument]
 pub async fn launch_minecraft(
-    username: String,
-    uuid: String,
-    access_token: String,
-    xbox_uid: String,
+    authentication_details: AuthenticationDetails,
     root: PathBuf,
     version_id: String,
+    options: LaunchOptions,
+    downloader: Downloader,
 ) -> Result<()> {
     info!("Launching minecraft");
 
-    let java_dir = if cfg!(windows) {
-        java_locator::locate_file("javaw.exe")?
-    } else {
-        java_locator::locate_file("java")?
-    };
-
-    let java_path = PathBuf::from(java_dir).join(if cfg!(windows) { "javaw.exe" } else { "java" });
-
     let id = if version_id == "latest" {
-        LauncherMeta::download_meta()
+        if options.offline {
+            bail!("The latest version can't be found while offline, specify a version instead");
+        }
+
+        LauncherMeta::download_meta(&downloader)
             .await
             .map_err(|err| anyhow!("Failed to download launcher meta: {}", err))?
             .latest
@@ -38,14 +48,37 @@ pub async fn launch_minecraft(
         version_id
     };
 
-    let authentication_details = AuthenticationDetails {
-        username,
-        uuid,
-        access_token,
-        xbox_uid,
-        client_id: None,
-        is_demo_user: false,
-    };
+    let version_manifest_path = root
+        .join("versions")
+        .join(&id)
+        .join(format!("{}.json", &id));
+
+    let manifest = fs::read_to_string(&version_manifest_path)
+        .await
+        .map_err(|err| anyhow!("Failed to read the version manifest of {}: {}", id, err))?;
+
+    // child profiles (e.g fabric) only get `javaVersion` from the version they inherit from
+    let (version, replaced_libraries) = VersionResolver::new(root.join("versions"))
+        .offline(options.offline)
+        .resolve_version_with_replaced(serde_json::from_str(&manifest)?, &downloader)
+        .await
+        .map_err(|err| {
+            anyhow!(
+                "Failed to resolve version manifest for version {}: {}",
+                id,
+                err
+            )
+        })?;
+
+    for library in &replaced_libraries {
+        info!(
+            "Using library {} instead of {} from a parent version",
+            library.kept, library.replaced
+        );
+    }
+
+    let java_path = find_java_path(&root, &id, &version).await?;
+    info!("Using java {}", java_path.display());
 
     let launcher = Launcher {
         assets_directory: root.join("assets"),
@@ -53,27 +86,25 @@ pub async fn launch_minecraft(
         custom_resolution: None,
         game_directory: root.clone(),
         is_snapshot: false,
-        jar_path: (&root)
-            .join("versions")
-            .join(&id)
-            .join(format!("{}.jar", &id)),
+        jar_path: root.join("versions").join(&id).join(format!("{}.jar", &id)),
         java_path,
         launcher_name: "minecraft.rs".to_string(),
         libraries_directory: root.join("libraries"),
+        natives_directory: options.natives_directory,
         ram_size: RamSize {
             min: "2024".to_string(),
             max: "4048".to_string(),
         },
-        version_manifest_path: root
-            .join("versions")
-            .join(&id)
-            .join(format!("{}.json", &id)),
+        version_manifest_path,
         version_name: id,
         client_branding: "minecraft.rs".to_string(),
+        offline: options.offline,
+        skip_log4j_mitigation: options.skip_log4j_mitigation,
+        java_version_check: options.java_version_check,
     };
 
     let game_output = launcher
-        .launch(None, create_client())
+        .launch(Some(version), &downloader)
         .await
         .map_err(|err| anyhow!("Failed to launch minecraft: {}", err))?;
 
@@ -82,7 +113,7 @@ pub async fn launch_minecraft(
     let mut out_buf = vec![];
     let mut err_buf = vec![];
 
-    while let Ok(_) = out_reader.read_until(b'\n', &mut out_buf).await {
+    while out_reader.read_until(b'\n', &mut out_buf).await.is_ok() {
         if out_buf.is_empty() {
             break;
         }
@@ -91,7 +122,7 @@ pub async fn launch_minecraft(
         out_buf.clear();
     }
 
-    while let Ok(_) = err_reader.read_until(b'\n', &mut err_buf).await {
+    while err_reader.read_until(b'\n', &mut err_buf).await.is_ok() {
         if err_buf.is_empty() {
             break;
         }
@@ -104,3 +135,38 @@ pub async fn launch_minecraft(
 
     Ok(())
 }
+
+/// The java to launch the resolved version `id` with: the runtime `download-dependencies`
+/// installed for it, or else a java installed on the system that can run it
+async fn find_java_path(root: &Path, id: &str, version: &Version) -> Result<PathBuf> {
+    let java_version = version.java_version.as_ref();
+
+    let component = java_version.map_or(DEFAULT_COMPONENT, |java_version| {
+        java_version.component.as_str()
+    });
+
+    if let Some(java_path) =
+        JavaRuntimeManager::new(root.join("runtime")).installed_java_path(component)
+    {
+        return Ok(java_path);
+    }
+
+    let installation = match java_version.and_then(|java_version| {
+        u32::try_from(java_version.major_version).ok()
+    }) {
+        Some(required) => find_java(required).await.ok_or_else(|| {
+            anyhow!(
+                "No java that can run version {} was found, install java {} or run download-dependencies",
+                id,
+                required
+            )
+        })?,
+        None => discover()
+            .await
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("No java was found, install java"))?,
+    };
+
+    Ok(installation.path)
+}
@@ -4,14 +4,14 @@ pub mod launch_minecraft;
 
 use anyhow::Result;
 use clap::Parser;
-use cli::{handle_args, Args};
+use cli::{handle_args, Cli};
 use tracing_subscriber::EnvFilter;
 
 
 #[tokio::main]
 async fn main() -> Result<()> {
     setup_logger();
-    let args = Args::try_parse()?;
+    let args = Cli::try_parse()?;
     handle_args(args).await
 }
 
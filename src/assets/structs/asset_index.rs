@@ -1,22 +1,28 @@
 use std::collections::HashMap;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use futures::stream::FuturesUnordered;
-use futures::StreamExt;
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use tokio::fs::create_dir_all;
-use tokio::sync::watch::{self, Sender};
-use tokio::task;
 use tracing::{debug, trace};
 
+use crate::downloader::{DownloadJob, Downloader};
 use crate::errors::SaveError;
-use crate::util::{
-    create_client, create_download_task, DownloadProgress, DownloadWatcher, ListOfResultHandles,
-};
+use crate::util::DownloadWatcher;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AssetIndex {
+    /// the assets are also needed by name in `assets/virtual/legacy`, used by 1.6 to 1.7.2
+    #[serde(
+        default,
+        rename = "virtual",
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub is_virtual: bool,
+    /// the assets are also needed by name in `<game_directory>/resources`, used before 1.6
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub map_to_resources: bool,
     pub objects: HashMap<String, Object>,
 }
 
@@ -39,7 +45,7 @@ impl AssetIndex {
         debug!("Creating AssetIndex file at {}", &save_path.display());
         let mut file = std::fs::File::create(&save_path)?;
         debug!("Writing JSON to AssetIndex file");
-        file.write(json.as_bytes())?;
+        file.write_all(json.as_bytes())?;
 
         debug!(
             "Saved AssetIndex to {}",
@@ -50,78 +56,100 @@ impl AssetIndex {
 
     /// The save path should be /assets/objects
     #[tracing::instrument]
-    pub async fn download_assets(&self, save_path: PathBuf) -> ListOfResultHandles {
-        trace!("Downloading assets");
-        let client = create_client();
-
-        let tasks = FuturesUnordered::new();
-        // create a final path and return it along with the url
-        let path_and_url: HashMap<String, String> = self
-            .objects
-            .iter()
-            .map(|(_path, object)| {
-                let url = format!(
+    pub fn asset_download_jobs(&self, save_path: PathBuf) -> Vec<DownloadJob> {
+        trace!("Creating asset download jobs");
+        // loop over the objects, creating a final path and url for each. Objects with the same
+        // hash are the same file, so they are only downloaded once
+        self.objects
+            .values()
+            .unique_by(|object| &object.hash)
+            .map(|object| DownloadJob {
+                url: format!(
                     "https://resources.download.minecraft.net/{}/{}",
                     &object.hash[..2],
                     object.hash
-                );
-
-                let download_path = format!("{}/{}", &object.hash[..2], object.hash);
-                (download_path, url)
+                ),
+                path: save_path.join(&object.hash[..2]).join(&object.hash),
+                sha1: Some(object.hash.clone()),
+                size: Some(object.size as u64),
             })
-            .collect();
-
-        // loop over the paths + urls
-        trace!("Creating asset download tasks");
-        for (path, url) in path_and_url.into_iter() {
-            // because the path includes the file name, we need to remove the last part
-            let full_path = save_path.join(path);
-            debug!("Creating download task for {}", &full_path.display());
-            tasks.push(create_download_task(url, full_path, Some(client.clone())));
-        }
+            .collect()
+    }
 
-        debug!("Created {} asset download tasks", tasks.len());
-        tasks
+    /// Where versions using this index look for the assets by name, if they're that old:
+    /// `<assets_directory>/virtual/legacy` or `<game_directory>/resources`
+    pub fn legacy_assets_directory(
+        &self,
+        assets_directory: &Path,
+        game_directory: &Path,
+    ) -> Option<PathBuf> {
+        if self.map_to_resources {
+            Some(game_directory.join("resources"))
+        } else if self.is_virtual {
+            Some(assets_directory.join("virtual").join("legacy"))
+        } else {
+            None
+        }
     }
 
-    #[tracing::instrument]
-    async fn run_downloads(
-        mut tasks: ListOfResultHandles,
-        progress_sender: Sender<DownloadProgress>,
-    ) {
-        trace!("Running asset download tasks");
-        let total = tasks.len();
-        let mut finished = 0;
-
-        while let Some(_) = tasks.next().await {
-            finished += 1;
-            debug!("{}/{} asset downloads finished", finished, total);
-            let _ = progress_sender.send(DownloadProgress {
-                total_size: total as u64,
-                finished,
-            });
+    /// Copies the downloaded assets from `<assets_directory>/objects` to their names in the
+    /// [legacy assets directory](Self::legacy_assets_directory), if this index has one. Assets
+    /// that are already there are left alone
+    #[tracing::instrument(skip(self))]
+    pub async fn copy_legacy_assets(
+        &self,
+        assets_directory: &Path,
+        game_directory: &Path,
+    ) -> Result<(), SaveError> {
+        let legacy_directory = match self.legacy_assets_directory(assets_directory, game_directory)
+        {
+            Some(legacy_directory) => legacy_directory,
+            None => return Ok(()),
+        };
+
+        debug!(
+            "Copying {} legacy assets to {}",
+            self.objects.len(),
+            legacy_directory.display()
+        );
+
+        for (name, object) in &self.objects {
+            let path = legacy_directory.join(name);
+
+            if tokio::fs::metadata(&path)
+                .await
+                .is_ok_and(|metadata| metadata.len() == object.size as u64)
+            {
+                continue;
+            }
+
+            let object_path = assets_directory
+                .join("objects")
+                .join(&object.hash[..2])
+                .join(&object.hash);
+
+            if !object_path.exists() {
+                return Err(SaveError::MissingAsset(name.clone()));
+            }
+
+            trace!("Copying {} to {}", object_path.display(), path.display());
+            create_dir_all(path.parent().ok_or(SaveError::NoParentPath)?).await?;
+            tokio::fs::copy(&object_path, &path).await?;
         }
 
-        debug!("All asset downloads finished");
+        Ok(())
     }
 
     #[tracing::instrument]
-    pub async fn start_download_assets(&self, save_path: PathBuf) -> DownloadWatcher {
+    pub async fn start_download_assets(
+        &self,
+        save_path: PathBuf,
+        downloader: &Downloader,
+    ) -> DownloadWatcher {
         trace!("Starting download assets");
-        trace!("Creating progress watcher");
-        let (progress_sender, progress_receiver) = watch::channel(DownloadProgress {
-            finished: 0,
-            total_size: 0,
-        });
-
-        trace!("Creating download tasks");
-        let tasks = self.download_assets(save_path).await;
-        trace!("Starting download tasks");
-        let download_task = task::spawn(Self::run_downloads(tasks, progress_sender));
-
-        DownloadWatcher {
-            progress_watcher: progress_receiver,
-            download_task,
-        }
+        let jobs = self.asset_download_jobs(save_path);
+        debug!("Created {} asset download jobs", jobs.len());
+
+        downloader.start(jobs)
     }
 }
@@ -1,4 +1,5 @@
 use super::version::Version as VersionManifest;
+use crate::downloader::Downloader;
 use serde::{Deserialize, Serialize};
 use tracing::{debug, trace};
 
@@ -39,13 +40,13 @@ pub enum Type {
 
 impl Version {
     #[tracing::instrument]
-    pub async fn version(&self) -> Result<VersionManifest, reqwest::Error> {
+    pub async fn version(
+        &self,
+        downloader: &Downloader,
+    ) -> Result<VersionManifest, reqwest::Error> {
         trace!("Downloading version manifest for {}", self.id);
         // download the version manifest and return a parsed version manifest
-        Ok(reqwest::get(&self.url)
-            .await?
-            .json::<VersionManifest>()
-            .await?)
+        downloader.get_json::<VersionManifest>(&self.url).await
     }
 }
 
@@ -56,8 +57,7 @@ impl Latest {
         launcher_meta
             .versions
             .iter()
-            .filter(|version| version.id == self.release)
-            .next()
+            .find(|version| version.id == self.release)
             .unwrap()
     }
 
@@ -67,21 +67,17 @@ impl Latest {
         launcher_meta
             .versions
             .iter()
-            .filter(|version| version.id == self.snapshot)
-            .next()
+            .find(|version| version.id == self.snapshot)
             .unwrap()
     }
 }
 
 impl LauncherMeta {
     #[tracing::instrument]
-    pub async fn download_meta() -> Result<Self, reqwest::Error> {
+    pub async fn download_meta(downloader: &Downloader) -> Result<Self, reqwest::Error> {
         let server_url = "https://launchermeta.mojang.com/mc/game/version_manifest_v2.json";
         debug!("Downloading launcher meta from {}", server_url);
 
-        Ok(reqwest::get(server_url)
-            .await?
-            .json::<LauncherMeta>()
-            .await?)
+        downloader.get_json::<LauncherMeta>(server_url).await
     }
 }
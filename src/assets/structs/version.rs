@@ -1,19 +1,19 @@
-use std::{fs::create_dir_all, io::Write, path::PathBuf};
+use std::{
+    collections::HashMap,
+    fs::create_dir_all,
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use futures::{stream::FuturesUnordered, StreamExt};
 use serde::{Deserialize, Serialize};
-use tokio::{
-    sync::watch::{self, Sender},
-    task,
-};
 use tracing::{debug, trace};
 
 use crate::{
-    errors::{DownloadError, VersionError},
-    util::{
-        create_client, create_download_task, create_library_download, DownloadProgress,
-        DownloadWatcher, ListOfResultHandles,
-    },
+    downloader::{DownloadJob, Downloader},
+    errors::VersionError,
+    maven::MavenRepositories,
+    util::{create_library_download, DownloadWatcher, MavenIdentifier},
 };
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -34,6 +34,10 @@ pub struct Version {
     pub logging: Option<Logging>,
     #[serde(rename = "mainClass")]
     pub main_class: Option<String>,
+    /// the game arguments of versions before 1.13, separated by spaces. Newer versions use
+    /// `arguments` instead
+    #[serde(rename = "minecraftArguments")]
+    pub minecraft_arguments: Option<String>,
     #[serde(rename = "minimumLauncherVersion")]
     pub minimum_launcher_version: Option<i64>,
     #[serde(rename = "releaseTime")]
@@ -43,9 +47,27 @@ pub struct Version {
     pub version_info_type: Option<String>,
 }
 
+/// A library from a lower priority manifest that was dropped while merging, because the higher
+/// priority manifest has the same library (group, artifact and classifier) too
+#[derive(Debug, Clone)]
+pub struct ReplacedLibrary {
+    /// the name of the library that was kept
+    pub kept: String,
+    /// the name of the library that was dropped
+    pub replaced: String,
+}
+
 impl Version {
+    /// Merges `lower` into this version, with this version taking priority
     #[tracing::instrument]
     pub fn merge(self, lower: Self) -> Self {
+        self.merge_with_replaced(lower).0
+    }
+
+    /// Merges `lower` into this version, with this version taking priority, and returns the
+    /// libraries of `lower` that were replaced by libraries of this version
+    #[tracing::instrument]
+    pub fn merge_with_replaced(self, lower: Self) -> (Self, Vec<ReplacedLibrary>) {
         let mut merged = Self {
             inherits_from: None,
             arguments: None,
@@ -58,6 +80,7 @@ impl Version {
             libraries: None,
             logging: None,
             main_class: None,
+            minecraft_arguments: None,
             minimum_launcher_version: None,
             release_time: None,
             time: None,
@@ -89,8 +112,13 @@ impl Version {
                 game: Some(game),
                 jvm: Some(jvm),
             })
+        } else {
+            merged.arguments = self.arguments;
         }
 
+        // legacy arguments (overriding, as they already include the arguments of the parent)
+        merged.minecraft_arguments = self.minecraft_arguments.or(lower.minecraft_arguments);
+
         // inheriting (overriding reverse)
         merged.inherits_from = lower.inherits_from.or(self.inherits_from);
 
@@ -112,14 +140,37 @@ impl Version {
         // java version (overriding)
         merged.java_version = self.java_version.or(lower.java_version);
 
-        // library (combining)
-        merged.libraries = Some(
-            self.libraries
-                .unwrap_or(vec![])
-                .into_iter()
-                .chain(lower.libraries.unwrap_or(vec![]).into_iter())
-                .collect(),
-        );
+        // logging (overriding)
+        merged.logging = self.logging.or(lower.logging);
+
+        // library (combining, libraries in both are overridden)
+        let libraries = self.libraries.unwrap_or(vec![]);
+        let mut replaced = vec![];
+        // libraries can be in the same manifest more than once with different rules, so only
+        // libraries from the lower manifest are dropped
+        let overrides = libraries
+            .iter()
+            .map(|library| (library_key(&library.name), library.name.clone()))
+            .collect::<HashMap<_, _>>();
+
+        let lower_libraries = lower
+            .libraries
+            .unwrap_or(vec![])
+            .into_iter()
+            .filter(|library| match overrides.get(&library_key(&library.name)) {
+                Some(kept) => {
+                    debug!("Library {} replaces {}", kept, library.name);
+                    replaced.push(ReplacedLibrary {
+                        kept: kept.clone(),
+                        replaced: library.name.clone(),
+                    });
+                    false
+                }
+                None => true,
+            })
+            .collect::<Vec<_>>();
+
+        merged.libraries = Some(libraries.into_iter().chain(lower_libraries).collect());
 
         // main class (overriding)
         merged.main_class = self.main_class.or(lower.main_class);
@@ -138,7 +189,7 @@ impl Version {
         // version info type (overriding)
         merged.version_info_type = self.version_info_type.or(lower.version_info_type);
 
-        merged
+        (merged, replaced)
     }
 
     #[tracing::instrument]
@@ -152,38 +203,73 @@ impl Version {
         debug!("Creating file at {}", save_path.display());
         let mut file = std::fs::File::create(&save_path)?;
         debug!("Writing version file to file");
-        file.write(json.as_bytes())?;
+        file.write_all(json.as_bytes())?;
 
         debug!("Saved version file to {}", &save_path.display());
         Ok(())
     }
 
     #[tracing::instrument]
-    pub async fn asset_index(&self) -> Result<super::asset_index::AssetIndex, VersionError> {
+    pub async fn asset_index(
+        &self,
+        downloader: &Downloader,
+    ) -> Result<super::asset_index::AssetIndex, VersionError> {
         trace!("Downloading asset index");
         // Get json and return it
-        Ok(reqwest::get(
-            &self
-                .asset_index
-                .as_ref()
-                .ok_or(VersionError::NoAssetIndex)?
-                .url,
-        )
-        .await?
-        .json::<super::asset_index::AssetIndex>()
-        .await?)
+        Ok(downloader
+            .get_json::<super::asset_index::AssetIndex>(
+                &self
+                    .asset_index
+                    .as_ref()
+                    .ok_or(VersionError::NoAssetIndex)?
+                    .url,
+            )
+            .await?)
     }
 
+    /// Resolves the downloads of every library that only has a maven `url` and `name` (e.g fabric
+    /// libraries) and stores them in the library, returning how many were resolved.
+    ///
+    /// Call this before [`Version::save_json`] so the saved manifest doesn't need any requests to
+    /// work out where the libraries are when downloading or launching
     #[tracing::instrument]
-    pub async fn download_libraries(
+    pub async fn resolve_library_downloads(
+        &mut self,
+        downloader: &Downloader,
+    ) -> Result<usize, VersionError> {
+        let mut resolved = 0;
+
+        for library in self.libraries.as_mut().ok_or(VersionError::NoLibs)? {
+            if library.downloads.is_some() {
+                continue;
+            }
+
+            if let Some(url) = &library.url {
+                debug!("Resolving downloads for library {}", library.name);
+                library.downloads = Some(
+                    create_library_download(
+                        &MavenRepositories::new([url]),
+                        &library.name,
+                        downloader,
+                    )
+                    .await?,
+                );
+                resolved += 1;
+            }
+        }
+
+        debug!("Resolved the downloads of {} libraries", resolved);
+        Ok(resolved)
+    }
+
+    #[tracing::instrument]
+    pub async fn library_download_jobs(
         &self,
         save_path: PathBuf,
-        client: reqwest::Client,
-    ) -> Result<ListOfResultHandles, VersionError> {
-        debug!("Downloading libraries");
-        let client = create_client();
-
-        let tasks = FuturesUnordered::new();
+        downloader: &Downloader,
+    ) -> Result<Vec<DownloadJob>, VersionError> {
+        debug!("Creating library download jobs");
+        let mut jobs = vec![];
 
         for library in self.libraries.as_ref().ok_or(VersionError::NoLibs)? {
             // Check rules for the library to see if it should be downloaded
@@ -205,123 +291,111 @@ impl Version {
                 down.to_owned()
             } else {
                 create_library_download(
-                    library.url.as_ref().unwrap(),
+                    &MavenRepositories::new(library.url.clone()),
                     &library.name,
-                    client.clone(),
+                    downloader,
                 )
                 .await?
             };
 
-            Self::create_save_task(&download.artifact, &save_path, library, &tasks, &client);
+            if let Some(artifact) = &download.artifact {
+                jobs.push(Self::library_download_job(artifact, &save_path, library));
+            }
 
-            if let Some(classifiers) = &download.classifiers {
-                match std::env::consts::OS {
-                    "windows" => {
-                        if let Some(windows) = &classifiers.natives_windows {
-                            Self::create_save_task(windows, &save_path, library, &tasks, &client);
-                        } else {
-                            continue;
-                        }
-                    }
-                    "macos" => {
-                        if let Some(macos) = &classifiers.natives_macos {
-                            Self::create_save_task(macos, &save_path, library, &tasks, &client);
-                        } else if let Some(osx) = &classifiers.natives_osx {
-                            Self::create_save_task(osx, &save_path, library, &tasks, &client);
-                        } else {
-                            continue;
-                        }
-                    }
-                    "linux" => {
-                        if let Some(linux) = &classifiers.natives_linux {
-                            Self::create_save_task(linux, &save_path, library, &tasks, &client);
-                        } else {
-                            continue;
-                        }
-                    }
-                    _ => return Err(VersionError::UnsupportedOs),
-                };
+            if let Some(natives) = library.natives_download(&download) {
+                jobs.push(Self::library_download_job(natives, &save_path, library));
             }
         }
 
-        debug!("Created {} library download tasks", tasks.len());
-        Ok(tasks)
-    }
-
-    #[tracing::instrument]
-    async fn run_downloads(
-        mut tasks: ListOfResultHandles,
-        progress_sender: Sender<DownloadProgress>,
-    ) {
-        trace!("Running library download tasks");
-        let total = tasks.len();
-        let mut finished = 0;
-
-        while let Some(_) = tasks.next().await {
-            finished += 1;
-            debug!("{}/{} library downloads finished", finished, total);
-            let _ = progress_sender.send(DownloadProgress {
-                total_size: total as u64,
-                finished,
-            });
-        }
+        debug!("Created {} library download jobs", jobs.len());
+        Ok(jobs)
     }
 
     #[tracing::instrument]
     pub async fn start_download_libraries(
         &self,
         save_path: PathBuf,
-        client: reqwest::Client,
+        downloader: &Downloader,
     ) -> Result<DownloadWatcher, VersionError> {
         trace!("Starting download libraries");
-        trace!("Creating progress watcher");
-        let (progress_sender, progress_receiver) = watch::channel(DownloadProgress {
-            finished: 0,
-            total_size: 0,
-        });
-
-        trace!("Creating download tasks");
-        let tasks = self.download_libraries(save_path, client).await?;
-        trace!("Starting download tasks");
-        let download_task = task::spawn(Self::run_downloads(tasks, progress_sender));
-
-        Ok(DownloadWatcher {
-            progress_watcher: progress_receiver,
-            download_task,
-        })
+        let jobs = self.library_download_jobs(save_path, downloader).await?;
+
+        Ok(downloader.start(jobs))
     }
 
     #[tracing::instrument]
-    pub async fn download_client_jar(&self, save_path: PathBuf) -> Result<(), VersionError> {
-        let url = self
+    pub async fn download_client_jar(
+        &self,
+        save_path: PathBuf,
+        downloader: &Downloader,
+    ) -> Result<(), VersionError> {
+        let client = &self
             .downloads
             .as_ref()
             .ok_or(VersionError::NoDownloads)?
-            .client
-            .url
-            .clone();
+            .client;
+
+        downloader
+            .download(DownloadJob {
+                url: client.url.clone(),
+                path: save_path,
+                sha1: Some(client.sha1.clone()),
+                size: Some(client.size),
+            })
+            .await??;
 
-        let task = tokio::spawn(create_download_task(url, save_path, None));
+        Ok(())
+    }
+
+    /// Downloads the log4j config of the client to `<assets_directory>/log_configs/<id>`.
+    ///
+    /// Does nothing for versions that don't have a logging config
+    #[tracing::instrument]
+    pub async fn download_logging_config(
+        &self,
+        assets_directory: PathBuf,
+        downloader: &Downloader,
+    ) -> Result<(), VersionError> {
+        let client = match &self.logging {
+            Some(logging) => &logging.client,
+            None => {
+                debug!("Version has no logging config");
+                return Ok(());
+            }
+        };
 
-        // the ultimate jank
-        task.await???;
+        downloader
+            .download(DownloadJob {
+                url: client.file.url.clone(),
+                path: client.config_path(&assets_directory),
+                sha1: Some(client.file.sha1.clone()),
+                size: u64::try_from(client.file.size).ok(),
+            })
+            .await??;
 
         Ok(())
     }
 
     #[tracing::instrument]
-    pub async fn download_server_jar(&self, save_path: PathBuf) -> Result<(), VersionError> {
-        let url = self
+    pub async fn download_server_jar(
+        &self,
+        save_path: PathBuf,
+        downloader: &Downloader,
+    ) -> Result<(), VersionError> {
+        let server = &self
             .downloads
             .as_ref()
             .ok_or(VersionError::NoDownloads)?
-            .server
-            .url
-            .clone();
-        let task = tokio::spawn(create_download_task(url, save_path, None));
-
-        // the ultimate jank
-        task.await???;
+            .server;
+
+        downloader
+            .download(DownloadJob {
+                url: server.url.clone(),
+                path: save_path,
+                sha1: Some(server.sha1.clone()),
+                size: Some(server.size),
+            })
+            .await??;
 
         Ok(())
     }
@@ -373,14 +447,11 @@ impl Version {
     }
 
     #[tracing::instrument]
-    fn create_save_task(
+    fn library_download_job(
         mappings_class: &MappingsClass,
         save_path: &PathBuf,
         library: &Library,
-        tasks: &FuturesUnordered<task::JoinHandle<Result<(), DownloadError>>>,
-        client: &reqwest::Client,
-    ) {
-        let url = mappings_class.url.clone();
+    ) -> DownloadJob {
         let sub_path = mappings_class
         .path
         .as_ref()
@@ -388,11 +459,30 @@ impl Version {
 
         let full_path = save_path.join(sub_path);
         debug!(
-            "Creating download task for library {}, saving to {}",
+            "Creating download job for library {}, saving to {}",
             library.name,
             full_path.display()
         );
-        tasks.push(create_download_task(url, full_path, Some(client.clone())));
+
+        DownloadJob {
+            url: mappings_class.url.clone(),
+            path: full_path,
+            sha1: Some(mappings_class.sha1.clone()),
+            size: Some(mappings_class.size),
+        }
+    }
+}
+
+/// What identifies a library when merging: its group, artifact and classifier, but not its version
+fn library_key(name: &str) -> (String, String, Option<String>) {
+    match MavenIdentifier::from_str(name) {
+        Ok(identifier) => (
+            identifier.group_id,
+            identifier.artifact_id,
+            identifier.classifier,
+        ),
+        // not a maven name, so it can only be the same library if the names are the same
+        Err(_) => (name.to_string(), String::new(), None),
     }
 }
 
@@ -452,9 +542,11 @@ pub struct AssetIndex {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VersionInfoDownloads {
     pub client: MappingsClass,
-    pub client_mappings: MappingsClass,
+    /// only there since 1.14.4
+    pub client_mappings: Option<MappingsClass>,
     pub server: MappingsClass,
-    pub server_mappings: MappingsClass,
+    /// only there since 1.14.4
+    pub server_mappings: Option<MappingsClass>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -482,9 +574,55 @@ pub struct Library {
     pub extract: Option<Extract>,
 }
 
+impl Library {
+    /// The classifier of the natives for the current OS and architecture (e.g
+    /// `natives-windows-64`), if the library lists any in `natives`
+    pub fn natives_classifier(&self) -> Option<String> {
+        let natives = self.natives.as_ref()?;
+        let classifier = match std::env::consts::OS {
+            "windows" => natives.windows.as_ref(),
+            "macos" => natives.osx.as_ref(),
+            "linux" => natives.linux.as_ref(),
+            _ => None,
+        }?;
+
+        let arch = if cfg!(target_pointer_width = "64") {
+            "64"
+        } else {
+            "32"
+        };
+
+        Some(classifier.replace("${arch}", arch))
+    }
+
+    /// The natives download for the current OS from the library's `downloads`, if it has natives
+    pub fn natives_download<'a>(
+        &self,
+        downloads: &'a LibraryDownloads,
+    ) -> Option<&'a MappingsClass> {
+        let classifiers = downloads.classifiers.as_ref()?;
+
+        match self.natives_classifier() {
+            Some(classifier) => classifiers.get(&classifier),
+            // no `natives`, so guess from the classifiers
+            None => match std::env::consts::OS {
+                "windows" => classifiers.natives_windows.as_ref(),
+                "macos" => classifiers
+                    .natives_macos
+                    .as_ref()
+                    .or(classifiers.natives_osx.as_ref()),
+                "linux" => classifiers.natives_linux.as_ref(),
+                _ => None,
+            },
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct LibraryDownloads {
-    pub artifact: MappingsClass,
+    /// the jar of the library. Libraries that only have natives (e.g LWJGL 2's `lwjgl-platform`)
+    /// don't have one
+    pub artifact: Option<MappingsClass>,
     pub classifiers: Option<Classifiers>,
 }
 
@@ -500,6 +638,24 @@ pub struct Classifiers {
     pub sources: Option<MappingsClass>,
     #[serde(rename = "natives-osx")]
     pub natives_osx: Option<MappingsClass>,
+    /// any other classifiers, e.g `natives-windows-64`
+    #[serde(flatten)]
+    pub other: HashMap<String, MappingsClass>,
+}
+
+impl Classifiers {
+    /// Gets a classifier by its name, e.g `natives-linux`
+    pub fn get(&self, classifier: &str) -> Option<&MappingsClass> {
+        match classifier {
+            "javadoc" => self.javadoc.as_ref(),
+            "natives-linux" => self.natives_linux.as_ref(),
+            "natives-macos" => self.natives_macos.as_ref(),
+            "natives-windows" => self.natives_windows.as_ref(),
+            "sources" => self.sources.as_ref(),
+            "natives-osx" => self.natives_osx.as_ref(),
+            _ => self.other.get(classifier),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -538,6 +694,18 @@ pub struct LoggingClient {
     pub client_type: String,
 }
 
+impl LoggingClient {
+    /// Where the log4j config is saved, `<assets_directory>/log_configs/<id>`
+    pub fn config_path(&self, assets_directory: &Path) -> PathBuf {
+        assets_directory.join("log_configs").join(&self.file.id)
+    }
+
+    /// The JVM argument that makes the game use the log4j config at `path`
+    pub fn jvm_argument(&self, path: &Path) -> String {
+        self.argument.replace("${path}", &path.to_string_lossy())
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum GameElement {
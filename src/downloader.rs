@@ -0,0 +1,622 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::stream::FuturesUnordered;
+use futures::{StreamExt, TryFutureExt};
+use reqwest::{Client, Method, Response, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use tokio::sync::watch::{self, Sender};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::task::{self, JoinError, JoinHandle};
+use tokio::time::interval;
+use tokio_retry::strategy::jitter;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, trace, warn};
+
+use crate::errors::{ClientError, DownloadError};
+use crate::util::{
+    create_client, download_file, is_already_downloaded, remove_if_exists, temp_download_path,
+    ClientConfig, DownloadOptions, DownloadProgress, DownloadWatcher,
+};
+
+/// How often the download speed is measured
+const SPEED_SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A single file to download
+#[derive(Debug, Clone)]
+pub struct DownloadJob {
+    /// where to download the file from
+    pub url: String,
+    /// where to save the file to, including the file name
+    pub path: PathBuf,
+    /// the expected SHA-1 of the file, if known
+    pub sha1: Option<String>,
+    /// the expected size of the file in bytes, if known
+    pub size: Option<u64>,
+}
+
+/// How failed downloads are retried
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// how many times a file is tried in total, including the first attempt
+    pub max_attempts: u32,
+    /// how long to wait between attempts
+    pub backoff: Backoff,
+    /// whether to randomize the delays, so lots of failed downloads don't all retry at once
+    pub jitter: bool,
+    /// HTTP status codes that are worth retrying. Any other unsuccessful status fails straight away
+    pub retryable_statuses: Vec<StatusCode>,
+    /// the longest a `Retry-After` header is allowed to make a download wait
+    pub max_retry_after: Duration,
+}
+
+/// How long to wait between download attempts
+#[derive(Debug, Clone)]
+pub enum Backoff {
+    /// wait the same amount of time after every attempt
+    Fixed(Duration),
+    /// start at `initial` and double after every attempt, up to `max`
+    Exponential { initial: Duration, max: Duration },
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            backoff: Backoff::Exponential {
+                initial: Duration::from_millis(250),
+                max: Duration::from_secs(10),
+            },
+            jitter: true,
+            retryable_statuses: vec![
+                StatusCode::REQUEST_TIMEOUT,
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            max_retry_after: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries
+    pub fn no_retries() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// How long to wait before trying again after `failed_attempts` attempts have failed with
+    /// `error`, or `None` if it shouldn't be tried again
+    pub fn retry_delay(&self, failed_attempts: u32, error: &DownloadError) -> Option<Duration> {
+        if failed_attempts >= self.max_attempts || !self.is_retryable(error) {
+            return None;
+        }
+
+        if let DownloadError::HttpStatus {
+            retry_after: Some(retry_after),
+            ..
+        } = error
+        {
+            return Some((*retry_after).min(self.max_retry_after));
+        }
+
+        let delay = match self.backoff {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { initial, max } => initial
+                .checked_mul(2u32.saturating_pow(failed_attempts.saturating_sub(1)))
+                .map_or(max, |delay| delay.min(max)),
+        };
+
+        Some(if self.jitter {
+            // keep at least half of the delay so retries don't end up happening immediately
+            delay / 2 + jitter(delay / 2)
+        } else {
+            delay
+        })
+    }
+
+    /// Whether a download that failed with `error` could succeed if it's tried again
+    pub fn is_retryable(&self, error: &DownloadError) -> bool {
+        match error {
+            DownloadError::RequestError(err) => !err.is_builder(),
+            DownloadError::HttpStatus { status, .. } => self.retryable_statuses.contains(status),
+            DownloadError::Sha1Mismatch { .. }
+            | DownloadError::SizeMismatch { .. }
+            | DownloadError::UnexpectedContentRange
+            | DownloadError::ReadTimeout => true,
+            DownloadError::NoPathParent
+            | DownloadError::IoError(_)
+            | DownloadError::JoinError(_)
+            | DownloadError::Cancelled => false,
+        }
+    }
+}
+
+/// Rewrites the hosts of URLs to point at mirrors, e.g. a BMCLAPI-style mirror or an internal cache
+///
+/// Anything fetched from a host with a mirror is fetched from the mirror first, falling back to the
+/// official host if the mirror fails.
+#[derive(Debug, Clone, Default)]
+pub struct Mirrors {
+    /// official host (e.g `resources.download.minecraft.net`) to the base URL of its mirror (e.g
+    /// `https://bmclapi2.bangbang93.com/assets`). The path of the original URL is appended to it
+    pub hosts: HashMap<String, String>,
+}
+
+impl Mirrors {
+    /// Mirrors everything through [BMCLAPI](https://bmclapidoc.bangbang93.com)
+    pub fn bmclapi() -> Self {
+        let bmclapi = "https://bmclapi2.bangbang93.com";
+
+        Self::default()
+            .with_mirror("launchermeta.mojang.com", bmclapi)
+            .with_mirror("launcher.mojang.com", bmclapi)
+            .with_mirror("piston-meta.mojang.com", bmclapi)
+            .with_mirror("piston-data.mojang.com", bmclapi)
+            .with_mirror(
+                "resources.download.minecraft.net",
+                format!("{}/assets", bmclapi),
+            )
+            .with_mirror("libraries.minecraft.net", format!("{}/maven", bmclapi))
+            .with_mirror("maven.fabricmc.net", format!("{}/maven", bmclapi))
+    }
+
+    /// Mirrors `host` through `mirror`
+    pub fn with_mirror(mut self, host: impl Into<String>, mirror: impl Into<String>) -> Self {
+        self.hosts.insert(host.into(), mirror.into());
+        self
+    }
+
+    /// The mirrored version of `url`, if its host has a mirror
+    pub fn rewrite(&self, url: &str) -> Option<String> {
+        let parsed = Url::parse(url).ok()?;
+        let mirror = self.hosts.get(parsed.host_str()?)?;
+
+        let mut rewritten = format!("{}{}", mirror.trim_end_matches('/'), parsed.path());
+        if let Some(query) = parsed.query() {
+            rewritten.push('?');
+            rewritten.push_str(query);
+        }
+
+        Some(rewritten)
+    }
+
+    /// The URLs to try for `url` in order: the mirror if there is one, then `url` itself
+    pub fn candidates(&self, url: &str) -> Vec<String> {
+        self.rewrite(url)
+            .into_iter()
+            .chain(std::iter::once(url.to_string()))
+            .collect()
+    }
+
+    /// Runs `fetch` with every candidate URL for `url` until one succeeds
+    async fn with_fallback<T, E, F, Fut>(&self, url: &str, fetch: F) -> Result<T, E>
+    where
+        E: Display,
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut candidates = self.candidates(url).into_iter().peekable();
+
+        loop {
+            let candidate = candidates
+                .next()
+                .expect("there is always at least one candidate");
+
+            match fetch(candidate.clone()).await {
+                Err(err) if candidates.peek().is_some() => {
+                    warn!("Failed to fetch {} ({}), falling back", candidate, err);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Downloads files using a shared client, limiting how many requests can be in flight at once.
+///
+/// Clones share the same limit, so the same downloader can be used for libraries, assets and the
+/// client jar at the same time without going over it. Requests made through [`Downloader::request`]
+/// and [`Downloader::get_json`] count towards it as well.
+///
+/// Every API in this crate that touches the network takes a downloader, so whatever the client
+/// was built with (proxies, root certificates, user agent, timeouts, ...) applies to all of it.
+#[derive(Debug, Clone)]
+pub struct Downloader {
+    client: Client,
+    limit: Arc<Semaphore>,
+    retry_policy: Arc<RetryPolicy>,
+    mirrors: Arc<Mirrors>,
+    read_timeout: Option<Duration>,
+}
+
+impl From<Client> for Downloader {
+    fn from(client: Client) -> Self {
+        Self::new(client, Self::DEFAULT_MAX_CONCURRENT_DOWNLOADS)
+    }
+}
+
+impl Downloader {
+    /// The amount of requests that can be in flight at once if nothing else is specified
+    pub const DEFAULT_MAX_CONCURRENT_DOWNLOADS: usize = 32;
+
+    /// Creates a new downloader. `max_concurrent_downloads` is clamped to at least 1
+    pub fn new(client: Client, max_concurrent_downloads: usize) -> Self {
+        Self {
+            client,
+            limit: Arc::new(Semaphore::new(max_concurrent_downloads.max(1))),
+            retry_policy: Arc::new(RetryPolicy::default()),
+            mirrors: Arc::new(Mirrors::default()),
+            read_timeout: None,
+        }
+    }
+
+    /// Creates a new downloader with the default client config and download limit
+    pub fn with_defaults() -> Result<Self, ClientError> {
+        Self::from_config(
+            &ClientConfig::default(),
+            Self::DEFAULT_MAX_CONCURRENT_DOWNLOADS,
+        )
+    }
+
+    /// Creates a new downloader with a client created from `config`
+    pub fn from_config(
+        config: &ClientConfig,
+        max_concurrent_downloads: usize,
+    ) -> Result<Self, ClientError> {
+        Ok(Self::new(create_client(config)?, max_concurrent_downloads)
+            .with_read_timeout(config.read_timeout))
+    }
+
+    /// Fails downloads that go without receiving anything for longer than `read_timeout`
+    pub fn with_read_timeout(mut self, read_timeout: Option<Duration>) -> Self {
+        self.read_timeout = read_timeout;
+        self
+    }
+
+    /// Fetches everything through `mirrors` where possible
+    pub fn with_mirrors(mut self, mirrors: Mirrors) -> Self {
+        self.mirrors = Arc::new(mirrors);
+        self
+    }
+
+    /// Uses `retry_policy` instead of the default one for all downloads
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = Arc::new(retry_policy);
+        self
+    }
+
+    /// The client used for all requests made by this downloader
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Sends a request to `url`, or its mirror if it has one. Unsuccessful statuses count as errors
+    ///
+    /// The request takes up a download slot until the response headers arrive, the body isn't
+    /// counted against the limit.
+    #[tracing::instrument]
+    pub async fn request(&self, method: Method, url: &str) -> Result<Response, reqwest::Error> {
+        let _permit = self.acquire_slot().await;
+
+        self.mirrors
+            .with_fallback(url, |url| {
+                self.client
+                    .request(method.clone(), url)
+                    .send()
+                    .and_then(|response| async { response.error_for_status() })
+            })
+            .await
+    }
+
+    /// Fetches JSON from `url`, or its mirror if it has one
+    #[tracing::instrument]
+    pub async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T, reqwest::Error> {
+        let _permit = self.acquire_slot().await;
+
+        // the mirror could send back something that isn't JSON, so parsing is part of the fallback
+        self.mirrors
+            .with_fallback(url, |url| async move {
+                self.client
+                    .get(url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<T>()
+                    .await
+            })
+            .await
+    }
+
+    /// Waits until a download slot is free, so requests made outside of downloads count towards the
+    /// limit too
+    async fn acquire_slot(&self) -> SemaphorePermit<'_> {
+        self.limit
+            .acquire()
+            .await
+            .expect("the download semaphore is never closed")
+    }
+
+    /// Downloads a single file once a download slot is free
+    #[tracing::instrument]
+    pub fn download(&self, job: DownloadJob) -> JoinHandle<Result<(), DownloadError>> {
+        self.download_with_progress(job, None, CancellationToken::new())
+    }
+
+    fn download_with_progress(
+        &self,
+        job: DownloadJob,
+        downloaded_bytes: Option<Arc<AtomicU64>>,
+        cancellation_token: CancellationToken,
+    ) -> JoinHandle<Result<(), DownloadError>> {
+        trace!("Creating download task for {}", job.url);
+        let client = self.client.clone();
+        let limit = self.limit.clone();
+        let retry_policy = self.retry_policy.clone();
+        let mirrors = self.mirrors.clone();
+        let read_timeout = self.read_timeout;
+
+        tokio::spawn(async move {
+            let download = async {
+                let _permit = limit
+                    .acquire_owned()
+                    .await
+                    .expect("the download semaphore is never closed");
+
+                let options = DownloadOptions {
+                    retry_policy: &retry_policy,
+                    read_timeout,
+                    downloaded_bytes: downloaded_bytes.as_deref(),
+                };
+
+                mirrors
+                    .with_fallback(&job.url, |url| {
+                        let (client, options) = (&client, &options);
+                        let job = DownloadJob { url, ..job.clone() };
+
+                        async move { download_file(client, &job, options).await }
+                    })
+                    .await
+            };
+
+            tokio::select! {
+                result = download => result,
+                _ = cancellation_token.cancelled() => {
+                    debug!("Download of {} was cancelled", job.url);
+                    remove_if_exists(&temp_download_path(&job.path)).await?;
+                    Err(DownloadError::Cancelled)
+                }
+            }
+        })
+    }
+
+    /// Downloads all the files in the background, reporting progress through the returned watcher.
+    ///
+    /// Files that already exist with the right size and SHA-1 are skipped and count as finished.
+    /// Any `.part` file they still have from an interrupted download is removed.
+    /// The downloads can be stopped with [`DownloadWatcher::cancel`].
+    ///
+    /// Jobs with the same path as an earlier job are dropped, since they would both write to the
+    /// same temp file at once.
+    #[tracing::instrument(skip(jobs))]
+    pub fn start(&self, jobs: Vec<DownloadJob>) -> DownloadWatcher {
+        let mut paths = HashSet::new();
+        let total = jobs.len();
+        let jobs = jobs
+            .into_iter()
+            .filter(|job| paths.insert(job.path.clone()))
+            .collect::<Vec<_>>();
+
+        if jobs.len() < total {
+            debug!("Dropped {} duplicate download jobs", total - jobs.len());
+        }
+
+        trace!("Creating progress watcher");
+        let (progress_sender, progress_receiver) = watch::channel(DownloadProgress::default());
+        let cancellation_token = CancellationToken::new();
+
+        trace!("Starting download tasks");
+        let download_task = task::spawn(self.clone().run_downloads(
+            jobs,
+            progress_sender,
+            cancellation_token.clone(),
+        ));
+
+        DownloadWatcher {
+            progress_watcher: progress_receiver,
+            download_task,
+            cancellation_token,
+        }
+    }
+
+    /// Whether `job` already exists with the right size and SHA-1. Checking takes up a download
+    /// slot, so not every file is read at once.
+    ///
+    /// If it does, any `.part` file left behind by a crashed download is removed, as nothing else
+    /// would ever clean it up
+    async fn is_already_downloaded(&self, job: &DownloadJob) -> bool {
+        let (sha1, size) = match (&job.sha1, job.size) {
+            (Some(sha1), Some(size)) => (sha1, size),
+            _ => return false,
+        };
+
+        let _permit = self.acquire_slot().await;
+
+        if !is_already_downloaded(&job.path, sha1, size).await {
+            return false;
+        }
+
+        if let Err(err) = remove_if_exists(&temp_download_path(&job.path)).await {
+            debug!(
+                "Couldn't remove stale part file of {}: {}",
+                job.path.display(),
+                err
+            );
+        }
+
+        true
+    }
+
+    async fn run_downloads(
+        self,
+        jobs: Vec<DownloadJob>,
+        progress_sender: Sender<DownloadProgress>,
+        cancellation_token: CancellationToken,
+    ) -> DownloadSummary {
+        let mut summary = DownloadSummary::default();
+        let downloaded_bytes = Arc::new(AtomicU64::new(0));
+        // kept apart from `downloaded_bytes` so they don't count towards the speed
+        let mut skipped_bytes = 0;
+        let mut progress = DownloadProgress {
+            total_size: jobs.len() as u64,
+            total_bytes: jobs.iter().filter_map(|job| job.size).sum(),
+            ..Default::default()
+        };
+        let mut tasks = FuturesUnordered::new();
+
+        let _ = progress_sender.send(progress);
+
+        for job in jobs {
+            if cancellation_token.is_cancelled() {
+                summary.cancelled.push(job);
+                continue;
+            }
+
+            let downloader = self.clone();
+            let downloaded_bytes = downloaded_bytes.clone();
+            let cancellation_token = cancellation_token.child_token();
+
+            // `None` if the file is already downloaded
+            tasks.push(async move {
+                if downloader.is_already_downloaded(&job).await {
+                    return (job, None);
+                }
+
+                let result = downloader
+                    .download_with_progress(job.clone(), Some(downloaded_bytes), cancellation_token)
+                    .await;
+                (job, Some(result))
+            });
+        }
+
+        debug!("Checking and downloading {} files", tasks.len());
+
+        let mut speed_interval = interval(SPEED_SAMPLE_INTERVAL);
+        let mut last_sample = (Instant::now(), downloaded_bytes.load(Ordering::Relaxed));
+
+        loop {
+            tokio::select! {
+                task = tasks.next() => match task {
+                    Some((job, None)) => {
+                        trace!("{} is already downloaded, skipped", job.path.display());
+                        progress.finished += 1;
+                        skipped_bytes += job.size.unwrap_or_default();
+                        summary.skipped.push(job);
+                    }
+                    Some((job, Some(result))) => {
+                        progress.finished += 1;
+                        debug!("{}/{} downloads finished", progress.finished, progress.total_size);
+                        summary.add_result(job, result);
+                    }
+                    None => break,
+                },
+                _ = speed_interval.tick() => {
+                    let now = (Instant::now(), downloaded_bytes.load(Ordering::Relaxed));
+                    let elapsed = now.0.duration_since(last_sample.0).as_secs_f64();
+                    if elapsed > 0.0 {
+                        progress.bytes_per_second =
+                            (now.1.saturating_sub(last_sample.1) as f64 / elapsed) as u64;
+                    }
+                    last_sample = now;
+                }
+            }
+
+            progress.downloaded_bytes = downloaded_bytes.load(Ordering::Relaxed) + skipped_bytes;
+            let _ = progress_sender.send(progress);
+        }
+
+        progress.downloaded_bytes = downloaded_bytes.load(Ordering::Relaxed) + skipped_bytes;
+        let _ = progress_sender.send(progress);
+
+        if cancellation_token.is_cancelled() {
+            summary.status = DownloadStatus::Cancelled;
+        }
+
+        debug!(
+            "All downloads finished ({:?}), {} succeeded, {} skipped, {} failed and {} cancelled",
+            summary.status,
+            summary.succeeded.len(),
+            summary.skipped.len(),
+            summary.failed.len(),
+            summary.cancelled.len()
+        );
+        summary
+    }
+}
+
+/// How a batch of downloads ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DownloadStatus {
+    /// every file was attempted
+    #[default]
+    Completed,
+    /// the downloads were cancelled before they all finished
+    Cancelled,
+}
+
+/// What happened to each file in a batch of downloads
+#[derive(Debug, Default)]
+pub struct DownloadSummary {
+    /// whether the downloads ran to the end or were cancelled
+    pub status: DownloadStatus,
+    /// files that were downloaded
+    pub succeeded: Vec<DownloadJob>,
+    /// files that were already downloaded and so were skipped
+    pub skipped: Vec<DownloadJob>,
+    /// files that couldn't be downloaded
+    pub failed: Vec<FailedDownload>,
+    /// files that weren't downloaded because the downloads were cancelled
+    pub cancelled: Vec<DownloadJob>,
+}
+
+/// A file that couldn't be downloaded, along with why
+#[derive(Debug)]
+pub struct FailedDownload {
+    pub job: DownloadJob,
+    pub error: DownloadError,
+}
+
+impl DownloadSummary {
+    /// Whether every file was downloaded or skipped
+    pub fn is_success(&self) -> bool {
+        self.status == DownloadStatus::Completed && self.failed.is_empty()
+    }
+
+    fn add_result(
+        &mut self,
+        job: DownloadJob,
+        result: Result<Result<(), DownloadError>, JoinError>,
+    ) {
+        match result
+            .map_err(DownloadError::from)
+            .and_then(|result| result)
+        {
+            Ok(()) => self.succeeded.push(job),
+            Err(DownloadError::Cancelled) => self.cancelled.push(job),
+            Err(error) => {
+                warn!("Failed to download {}: {}", job.url, error);
+                self.failed.push(FailedDownload { job, error });
+            }
+        }
+    }
+}
@@ -70,6 +70,59 @@ pub enum DownloadError {
     #[error("download.request_error(error={0})")]
     /// An error happened with reqwest.
     RequestError(#[from] reqwest::Error),
+
+    #[error("download.sha1_mismatch(expected={expected}, actual={actual})")]
+    /// The downloaded file doesn't have the SHA-1 hash the manifest says it should have
+    Sha1Mismatch { expected: String, actual: String },
+
+    #[error("download.size_mismatch(expected={expected}, actual={actual})")]
+    /// The downloaded file doesn't have the size the manifest says it should have
+    SizeMismatch { expected: u64, actual: u64 },
+
+    #[error("download.http_status(status={status})")]
+    /// The server responded with an unsuccessful status code
+    HttpStatus {
+        status: reqwest::StatusCode,
+        /// how long the server asked to wait before trying again, if it did
+        retry_after: Option<std::time::Duration>,
+    },
+
+    #[error("download.unexpected_content_range")]
+    /// The server sent back a different part of the file than what was asked for
+    UnexpectedContentRange,
+
+    #[error("download.join_error(error={0})")]
+    /// The download task panicked or was aborted
+    JoinError(#[from] tokio::task::JoinError),
+
+    #[error("download.read_timeout")]
+    /// The server didn't send anything for longer than the read timeout
+    ReadTimeout,
+
+    #[error("download.cancelled")]
+    /// The download was cancelled before it finished
+    Cancelled,
+}
+
+#[derive(Debug, Error)]
+/// Errors relating to creating the HTTP client
+pub enum ClientError {
+    #[error("client.invalid_proxy(error={0})")]
+    /// The proxy URL isn't valid
+    InvalidProxy(reqwest::Error),
+
+    #[error("client.invalid_certificate(error={0})")]
+    /// One of the root certificates couldn't be parsed
+    InvalidCertificate(reqwest::Error),
+
+    #[error("client.no_certificates")]
+    /// One of the root certificate PEMs doesn't contain any certificates
+    NoCertificates,
+
+    #[error("client.build_error(error={0})")]
+    /// reqwest couldn't create the client, e.g because the user agent isn't a valid header or the
+    /// TLS backend couldn't be initialized
+    BuildError(reqwest::Error),
 }
 
 #[derive(Debug, Error)]
@@ -106,6 +159,92 @@ pub enum LauncherError {
     ///
     /// This usually happens when you forget to merge e.g A manifest that doesn't have any new args with the base one
     NoArgs,
+
+    #[error("launcher.no_versions_directory")]
+    /// The version manifest path isn't inside a versions folder (`versions/<id>/<id>.json`), so
+    /// the versions it inherits from can't be found
+    NoVersionsDirectory,
+
+    #[error("launcher.version_resolve_error(error={0})")]
+    /// The version manifest or one of the versions it inherits from couldn't be resolved
+    VersionResolveError(#[from] VersionResolveError),
+
+    #[error("launcher.save_error(error={0})")]
+    /// Copying the assets of a version with a legacy asset index failed
+    SaveError(#[from] SaveError),
+
+    #[error("launcher.natives_error(error={0})")]
+    /// The natives couldn't be extracted
+    NativesError(#[from] NativesError),
+
+    #[error("launcher.download_error(error={0})")]
+    /// A file needed to launch couldn't be downloaded
+    DownloadError(#[from] DownloadError),
+
+    #[error("launcher.java_discovery_error(error={0})")]
+    /// The java executable couldn't be inspected to check its version
+    JavaDiscoveryError(#[from] JavaDiscoveryError),
+
+    #[error("launcher.incompatible_java(path={}, required={required}, found={found})", .path.display())]
+    /// The java executable is too old to run the version, e.g java 8 for 1.18
+    IncompatibleJava {
+        path: std::path::PathBuf,
+        required: u32,
+        found: u32,
+    },
+
+    #[error("launcher.missing_log4j_config(path={})", .0.display())]
+    /// The version needs a patched log4j config to be safe from Log4Shell, but it isn't downloaded
+    /// and the launcher is offline
+    MissingLog4jConfig(std::path::PathBuf),
+}
+
+#[derive(Debug, Error)]
+/// Errors relating to extracting natives
+pub enum NativesError {
+    #[error("natives.io_error(error={0})")]
+    /// An error happened during an IO operation
+    IoError(#[from] std::io::Error),
+
+    #[error("natives.zip_error(error={0})")]
+    /// A natives jar couldn't be read
+    ZipError(#[from] zip::result::ZipError),
+
+    #[error("natives.join_error")]
+    /// An error happened when trying to join/wait for a threads output
+    JoinError(#[from] tokio::task::JoinError),
+
+    #[error("natives.no_path(library={0})")]
+    /// The natives download of a library doesn't have a path
+    NoPath(String),
+
+    #[error("natives.missing_file(path={})", .0.display())]
+    /// A natives jar isn't on disk. It has to be downloaded before launching
+    MissingFile(std::path::PathBuf),
+}
+
+#[derive(Debug, Error)]
+/// Errors relating to resolving the versions a version inherits from
+pub enum VersionResolveError {
+    #[error("version_resolve.io_error(error={0})")]
+    /// An error happened during an IO operation
+    IoError(#[from] std::io::Error),
+
+    #[error("version_resolve.serde_error(error={0})")]
+    /// serde_json failed to serialize/deserialize an error
+    SerdeError(#[from] serde_json::Error),
+
+    #[error("version_resolve.request_error(error={0})")]
+    /// An error happened with reqwest.
+    RequestError(#[from] reqwest::Error),
+
+    #[error("version_resolve.not_found(id={0})")]
+    /// The version isn't on disk or in the launcher meta
+    NotFound(String),
+
+    #[error("version_resolve.cycle(chain={0})")]
+    /// The versions inherit from each other in a loop
+    Cycle(String),
 }
 
 #[derive(Debug, Error)]
@@ -128,6 +267,10 @@ pub enum JavaArgumentsError {
     /// An error happened during an IO operation
     IoError(#[from] std::io::Error),
 
+    #[error("java_arguments.serde_error(error={0})")]
+    /// serde_json failed to serialize/deserialize an error
+    SerdeError(#[from] serde_json::Error),
+
     #[error("java_arguments.no_download_artifact_path")]
     /// a download artifact path was not provided by the version manifest
     ///
@@ -135,6 +278,14 @@ pub enum JavaArgumentsError {
     /// download manifest path with the base one
     NoDownloadArtifactPath,
 
+    #[error("java_arguments.missing_file(path={})", .0.display())]
+    /// A file needed to launch the game isn't on disk. It has to be downloaded before launching
+    MissingFile(std::path::PathBuf),
+
+    #[error("java_arguments.maven_identifier_parse_error(error={0})")]
+    /// A library without downloads doesn't have a valid maven identifier as its name
+    MavenIdentifierParseError(#[from] MavenIdentifierParseError),
+
     #[error("java_arguments.no_libs_path")]
     /// No lib path was found
     ///
@@ -201,13 +352,45 @@ pub enum SaveError {
     #[error("save.not_valid_utf8_path")]
     /// A path is not valid UTF-8.
     NotValidUtf8Path,
+
+    #[error("save.missing_asset(name={0})")]
+    /// An asset that has to be copied by name isn't downloaded
+    MissingAsset(String),
 }
 
 #[derive(Error, Debug)]
 pub enum MavenIdentifierParseError {
     #[error("maven_parse.not_enough_args")]
     /// There were not enough `:` in the string to properly parse it
-    NotEnoughArgs
+    NotEnoughArgs,
+
+    #[error("maven_parse.too_many_args")]
+    /// There were more `:` in the string than a group, artifact, version and classifier need
+    TooManyArgs,
+
+    #[error("maven_parse.empty_part")]
+    /// One of the parts of the string (or the extension after the `@`) is empty
+    EmptyPart,
+}
+
+#[derive(Error, Debug)]
+/// Errors relating to looking up artifacts in maven repositories
+pub enum MavenError {
+    #[error("maven.request_error(error={0})")]
+    /// An error happened with reqwest.
+    RequestError(#[from] reqwest::Error),
+
+    #[error("maven.xml_error(error={0})")]
+    /// A `maven-metadata.xml` couldn't be parsed
+    XmlError(#[from] quick_xml::DeError),
+
+    #[error("maven.not_found(artifact={0})")]
+    /// None of the repositories have the artifact
+    NotFound(String),
+
+    #[error("maven.no_versions(artifact={0})")]
+    /// The metadata doesn't list any versions to pick from
+    NoVersions(String),
 }
 
 #[derive(Error, Debug)]
@@ -220,6 +403,10 @@ pub enum CreateLibraryDownloadError {
     /// An error happene during a maven parse
     MavenParseError(#[from] MavenIdentifierParseError),
 
+    #[error("library_download.maven_error(error={0})")]
+    /// The library couldn't be found in any of the repositories
+    MavenError(#[from] MavenError),
+
     #[error("library_download.no_content_length_header")]
     /// No content-length was provided from the HEAD request made to the maven server
     NoContentLength,
@@ -228,3 +415,68 @@ pub enum CreateLibraryDownloadError {
     /// content-length is not a valid number 
     CannotParseContentLength
 }
+
+#[derive(Debug, Error)]
+/// Errors relating to installing a java runtime
+pub enum JavaRuntimeError {
+    #[error("java_runtime.io_error(error={0})")]
+    /// An error happened during an IO operation
+    IoError(#[from] std::io::Error),
+
+    #[error("java_runtime.request_error(error={0})")]
+    /// The runtime index or a runtime manifest couldn't be downloaded
+    RequestError(#[from] reqwest::Error),
+
+    #[error("java_runtime.join_error")]
+    /// An error happened when trying to join/wait for a threads output
+    JoinError(#[from] tokio::task::JoinError),
+
+    #[error("java_runtime.lzma_error(error={0})")]
+    /// A compressed file couldn't be decompressed
+    LzmaError(#[from] lzma_rs::error::Error),
+
+    #[error("java_runtime.unsupported_platform(platform={0})")]
+    /// The runtime index doesn't have any runtimes for this platform, e.g linux on arm
+    UnsupportedPlatform(String),
+
+    #[error("java_runtime.not_found(component={component}, platform={platform})")]
+    /// The runtime index doesn't have the component for this platform, e.g there is no
+    /// `java-runtime-gamma` for 32 bit linux
+    NotFound { component: String, platform: String },
+
+    #[error("java_runtime.invalid_path(path={0})")]
+    /// A file or link in the runtime manifest would end up or point outside of the runtime folder
+    InvalidPath(String),
+
+    #[error("java_runtime.sha1_mismatch(path={}, expected={expected}, actual={actual})", .path.display())]
+    /// A decompressed file doesn't have the SHA-1 the manifest says it should have
+    Sha1Mismatch {
+        path: std::path::PathBuf,
+        expected: String,
+        actual: String,
+    },
+
+    #[error("java_runtime.download_failed(failed={0})")]
+    /// Some files of the runtime couldn't be downloaded
+    DownloadFailed(usize),
+
+    #[error("java_runtime.cancelled")]
+    /// The downloads were cancelled before the runtime was installed
+    Cancelled,
+}
+
+#[derive(Debug, Error)]
+/// Errors relating to finding java installations
+pub enum JavaDiscoveryError {
+    #[error("java_discovery.io_error(error={0})")]
+    /// The java executable couldn't be run
+    IoError(#[from] std::io::Error),
+
+    #[error("java_discovery.no_version(path={})", .0.display())]
+    /// The java executable didn't say which version it is
+    NoVersion(std::path::PathBuf),
+
+    #[error("java_discovery.invalid_version(version={0})")]
+    /// The java version isn't in a known format
+    InvalidVersion(String),
+}
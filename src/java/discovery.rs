@@ -0,0 +1,282 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use futures::future::join_all;
+use tokio::process::Command;
+use tracing::{debug, trace};
+
+use crate::errors::JavaDiscoveryError;
+
+/// The name of the java executable that is used to launch the game
+#[cfg(windows)]
+pub const JAVA_EXECUTABLE: &str = "javaw.exe";
+/// The name of the java executable that is used to launch the game
+#[cfg(not(windows))]
+pub const JAVA_EXECUTABLE: &str = "java";
+
+/// Folders that have java installations in them, one per subfolder
+#[cfg(target_os = "linux")]
+const JAVA_FOLDERS: &[&str] = &["/usr/lib/jvm", "/usr/lib64/jvm", "/usr/java", "/opt/java"];
+#[cfg(target_os = "macos")]
+const JAVA_FOLDERS: &[&str] = &[
+    "/Library/Java/JavaVirtualMachines",
+    "/System/Library/Java/JavaVirtualMachines",
+];
+#[cfg(windows)]
+const JAVA_FOLDERS: &[&str] = &[
+    "C:\\Program Files\\Java",
+    "C:\\Program Files (x86)\\Java",
+    "C:\\Program Files\\Eclipse Adoptium",
+    "C:\\Program Files\\Zulu",
+    "C:\\Program Files\\Microsoft",
+];
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+const JAVA_FOLDERS: &[&str] = &[];
+
+/// A java installation and what it reported about itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JavaInstallation {
+    /// the java executable
+    pub path: PathBuf,
+    /// the full version, e.g `17.0.3` or `1.8.0_312`
+    pub version: String,
+    /// the major version, e.g 17 or 8
+    pub major_version: u32,
+    /// who made it, e.g `Eclipse Adoptium`
+    pub vendor: Option<String>,
+    /// the architecture it runs on, e.g `amd64` or `aarch64`
+    pub arch: Option<String>,
+}
+
+/// How well a java fits a version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JavaCompatibility {
+    /// the java the version needs, or a newer one that is known to work
+    Compatible,
+    /// newer than the java 8 the version needs. Versions that use LWJGL 2 or launchwrapper (1.12.2
+    /// and older) break on it, newer ones run fine
+    Newer,
+    /// too old to run the version at all
+    TooOld,
+}
+
+impl JavaInstallation {
+    /// How well this java fits a version that needs `required_major_version`
+    pub fn compatibility(&self, required_major_version: u32) -> JavaCompatibility {
+        compatibility(required_major_version, self.major_version)
+    }
+
+    /// Whether this java is new enough for a version that needs `required_major_version`
+    pub fn is_compatible(&self, required_major_version: u32) -> bool {
+        is_compatible(required_major_version, self.major_version)
+    }
+}
+
+/// How well java `major_version` fits a version that needs `required_major_version`
+pub fn compatibility(required_major_version: u32, major_version: u32) -> JavaCompatibility {
+    if major_version < required_major_version {
+        JavaCompatibility::TooOld
+    } else if required_major_version <= 8 && major_version > required_major_version {
+        JavaCompatibility::Newer
+    } else {
+        JavaCompatibility::Compatible
+    }
+}
+
+/// Whether java `major_version` is new enough for a version that needs `required_major_version`.
+///
+/// Java newer than 8 is allowed for versions that need 8, as only the ones before 1.13 break on
+/// it. [`compatibility`] tells them apart
+pub fn is_compatible(required_major_version: u32, major_version: u32) -> bool {
+    compatibility(required_major_version, major_version) != JavaCompatibility::TooOld
+}
+
+/// Runs `java -XshowSettings:properties -version` to find out the version, vendor and
+/// architecture of a java executable
+#[tracing::instrument]
+pub async fn inspect(java_path: &Path) -> Result<JavaInstallation, JavaDiscoveryError> {
+    let output = Command::new(java_path)
+        .arg("-XshowSettings:properties")
+        .arg("-version")
+        .output()
+        .await?;
+
+    // the settings are printed to stderr
+    let properties = String::from_utf8_lossy(&output.stderr);
+    let property = |name: &str| {
+        properties.lines().find_map(|line| {
+            let (key, value) = line.trim().split_once(" = ")?;
+            (key == name).then(|| value.trim().to_string())
+        })
+    };
+
+    let version = property("java.version")
+        .ok_or_else(|| JavaDiscoveryError::NoVersion(java_path.to_path_buf()))?;
+    let major_version = parse_major_version(&version)
+        .ok_or_else(|| JavaDiscoveryError::InvalidVersion(version.clone()))?;
+
+    let installation = JavaInstallation {
+        path: java_path.to_path_buf(),
+        version,
+        major_version,
+        vendor: property("java.vendor"),
+        arch: property("os.arch"),
+    };
+
+    trace!("Found {:?}", installation);
+    Ok(installation)
+}
+
+/// Finds every java installation in `JAVA_HOME`, `PATH` and the folders java is usually installed
+/// to (e.g `/usr/lib/jvm`). Executables that can't be run are left out
+#[tracing::instrument]
+pub async fn discover() -> Vec<JavaInstallation> {
+    let mut seen = HashSet::new();
+
+    // the same java is often in more than one place, e.g `/usr/bin/java` links to `/usr/lib/jvm`
+    let candidates = candidates()
+        .into_iter()
+        .filter(|path| path.is_file())
+        .filter(|path| seen.insert(dunce::canonicalize(path).unwrap_or_else(|_| path.clone())))
+        .collect::<Vec<_>>();
+
+    debug!("Inspecting {} java executables", candidates.len());
+    join_all(candidates.iter().map(|path| inspect(path)))
+        .await
+        .into_iter()
+        .zip(&candidates)
+        .filter_map(|(installation, path)| match installation {
+            Ok(installation) => Some(installation),
+            Err(err) => {
+                debug!("Couldn't inspect {}: {}", path.display(), err);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Finds the java installation that fits a version that needs `required_major_version` best: one
+/// with the same major version, or else the oldest one that is compatible
+#[tracing::instrument]
+pub async fn find_java(required_major_version: u32) -> Option<JavaInstallation> {
+    select_java(discover().await, required_major_version)
+}
+
+/// Picks the installation that fits a version that needs `required_major_version` best out of
+/// `installations`
+pub fn select_java(
+    installations: Vec<JavaInstallation>,
+    required_major_version: u32,
+) -> Option<JavaInstallation> {
+    installations
+        .into_iter()
+        .filter(|installation| installation.is_compatible(required_major_version))
+        .min_by_key(|installation| installation.major_version)
+}
+
+/// Parses the major version out of a java version, e.g 8 for `1.8.0_312` and 17 for `17.0.3`
+pub fn parse_major_version(version: &str) -> Option<u32> {
+    let mut parts = version.split(|c: char| !c.is_ascii_digit());
+
+    match parts.next()?.parse().ok()? {
+        // versions before java 9 are `1.<major>`
+        1 => parts.next()?.parse().ok(),
+        major => Some(major),
+    }
+}
+
+/// Every place java might be installed, whether it exists or not
+fn candidates() -> Vec<PathBuf> {
+    let mut candidates = vec![];
+
+    if let Some(java_home) = std::env::var_os("JAVA_HOME") {
+        candidates.push(PathBuf::from(java_home).join("bin").join(JAVA_EXECUTABLE));
+    }
+
+    if let Some(path) = std::env::var_os("PATH") {
+        candidates.extend(std::env::split_paths(&path).map(|path| path.join(JAVA_EXECUTABLE)));
+    }
+
+    for folder in JAVA_FOLDERS {
+        let entries = match std::fs::read_dir(folder) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.flatten() {
+            let home = entry.path();
+            candidates.push(home.join("bin").join(JAVA_EXECUTABLE));
+            // macOS bundles
+            candidates.push(
+                home.join("Contents")
+                    .join("Home")
+                    .join("bin")
+                    .join(JAVA_EXECUTABLE),
+            );
+        }
+    }
+
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn installation(major_version: u32) -> JavaInstallation {
+        JavaInstallation {
+            path: PathBuf::from(format!("/usr/lib/jvm/java-{}/bin/java", major_version)),
+            version: major_version.to_string(),
+            major_version,
+            vendor: None,
+            arch: None,
+        }
+    }
+
+    #[test]
+    fn parses_major_versions() {
+        assert_eq!(parse_major_version("1.8.0_352"), Some(8));
+        assert_eq!(parse_major_version("17.0.2"), Some(17));
+        assert_eq!(parse_major_version("21"), Some(21));
+        assert_eq!(parse_major_version("21-ea"), Some(21));
+        assert_eq!(parse_major_version("not a version"), None);
+    }
+
+    #[test]
+    fn only_java_that_is_too_old_is_incompatible() {
+        assert_eq!(compatibility(8, 8), JavaCompatibility::Compatible);
+        assert_eq!(compatibility(8, 17), JavaCompatibility::Newer);
+        assert_eq!(compatibility(17, 17), JavaCompatibility::Compatible);
+        assert_eq!(compatibility(17, 21), JavaCompatibility::Compatible);
+        assert_eq!(compatibility(17, 8), JavaCompatibility::TooOld);
+        assert_eq!(compatibility(16, 11), JavaCompatibility::TooOld);
+
+        assert!(is_compatible(8, 17));
+        assert!(!is_compatible(17, 16));
+    }
+
+    #[test]
+    fn selects_the_same_major_version_first() {
+        let installations = vec![installation(21), installation(8), installation(17)];
+
+        assert_eq!(
+            select_java(installations, 8).map(|java| java.major_version),
+            Some(8)
+        );
+    }
+
+    #[test]
+    fn selects_the_oldest_compatible_java() {
+        let installations = vec![installation(21), installation(11), installation(17)];
+
+        assert_eq!(
+            select_java(installations.clone(), 8).map(|java| java.major_version),
+            Some(11)
+        );
+        assert_eq!(
+            select_java(installations.clone(), 16).map(|java| java.major_version),
+            Some(17)
+        );
+        assert_eq!(select_java(installations, 22), None);
+    }
+}
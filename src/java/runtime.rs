@@ -0,0 +1,475 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use tracing::{debug, trace};
+
+use crate::assets::structs::version::Version;
+use crate::downloader::{DownloadJob, DownloadStatus, Downloader};
+use crate::errors::JavaRuntimeError;
+use crate::util::{is_already_downloaded, remove_if_exists, temp_download_path};
+
+/// Mojang's index of every java runtime for every platform
+pub const JAVA_RUNTIME_INDEX_URL: &str = "https://launchermeta.mojang.com/v1/products/java-runtime/2ec0cc96c44e5a76b9c8b7c39df7210883d12871/all.json";
+
+/// The runtime used by versions that don't say which one they need
+pub const DEFAULT_COMPONENT: &str = "jre-legacy";
+
+/// The file in an installed runtime that records which manifest it was installed from
+const MARKER_FILE: &str = ".copper-runtime";
+
+/// `all.json`, the runtimes of every component (e.g `java-runtime-gamma`) for every platform (e.g
+/// `linux` or `windows-x64`)
+pub type RuntimeIndex = HashMap<String, HashMap<String, Vec<Runtime>>>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Runtime {
+    pub availability: RuntimeAvailability,
+    /// the manifest with every file of the runtime
+    pub manifest: RuntimeDownload,
+    pub version: RuntimeVersion,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuntimeAvailability {
+    pub group: i64,
+    pub progress: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuntimeVersion {
+    /// the java version, e.g `17.0.3`
+    pub name: String,
+    pub released: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuntimeDownload {
+    pub sha1: String,
+    pub size: u64,
+    pub url: String,
+}
+
+/// Every file, folder and link of a runtime
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuntimeManifest {
+    /// the path of each entry relative to the runtime folder, e.g `bin/java`
+    pub files: HashMap<String, RuntimeFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum RuntimeFile {
+    Directory,
+    File {
+        #[serde(default)]
+        executable: bool,
+        downloads: RuntimeFileDownloads,
+    },
+    Link {
+        /// where the link points to, relative to the folder the link is in
+        target: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuntimeFileDownloads {
+    pub raw: RuntimeDownload,
+    /// the file compressed with LZMA, which is downloaded instead of the raw file if it's there
+    pub lzma: Option<RuntimeDownload>,
+}
+
+/// Installs the java runtimes Mojang publishes, so the game doesn't need a java installed on the
+/// system.
+///
+/// Runtimes are installed to `<runtime_directory>/<component>/<platform>/<component>`, the same
+/// place the official launcher puts them
+#[derive(Debug, Clone)]
+pub struct JavaRuntimeManager {
+    /// the folder runtimes are installed to, e.g `.minecraft/runtime`
+    pub runtime_directory: PathBuf,
+    /// where to get the runtime index (`all.json`) from
+    pub index_url: String,
+    /// the platform to install runtimes for, e.g `linux` or `windows-x64`
+    pub platform: String,
+}
+
+impl JavaRuntimeManager {
+    /// Creates a manager that installs runtimes for the current platform to `runtime_directory`.
+    ///
+    /// On platforms Mojang doesn't publish runtimes for, the platform is `<os>-<arch>` (e.g
+    /// `linux-aarch64`), so installing fails with [`JavaRuntimeError::UnsupportedPlatform`]
+    pub fn new(runtime_directory: PathBuf) -> Self {
+        let platform = current_platform().map_or_else(
+            || format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH),
+            str::to_string,
+        );
+
+        Self {
+            runtime_directory,
+            index_url: JAVA_RUNTIME_INDEX_URL.to_string(),
+            platform,
+        }
+    }
+
+    /// Gets the runtime index from somewhere else, e.g a mirror
+    pub fn with_index_url(mut self, index_url: impl Into<String>) -> Self {
+        self.index_url = index_url.into();
+        self
+    }
+
+    /// Installs runtimes for another platform
+    pub fn with_platform(mut self, platform: impl Into<String>) -> Self {
+        self.platform = platform.into();
+        self
+    }
+
+    /// Downloads the runtime index
+    #[tracing::instrument]
+    pub async fn index(&self, downloader: &Downloader) -> Result<RuntimeIndex, JavaRuntimeError> {
+        trace!("Downloading the java runtime index");
+        Ok(downloader.get_json(&self.index_url).await?)
+    }
+
+    /// The folder a component is installed to
+    pub fn component_directory(&self, component: &str) -> PathBuf {
+        self.runtime_directory
+            .join(component)
+            .join(&self.platform)
+            .join(component)
+    }
+
+    /// The path of the `java` executable of a component, whether it's installed or not
+    pub fn java_path(&self, component: &str) -> PathBuf {
+        let directory = self.component_directory(component);
+
+        if self.platform.starts_with("mac-os") {
+            directory.join("jre.bundle/Contents/Home/bin/java")
+        } else if self.platform.starts_with("windows") {
+            directory.join("bin").join("javaw.exe")
+        } else {
+            directory.join("bin").join("java")
+        }
+    }
+
+    /// The path of the `java` executable of a component if it's installed
+    pub fn installed_java_path(&self, component: &str) -> Option<PathBuf> {
+        let java_path = self.java_path(component);
+
+        if self
+            .component_directory(component)
+            .join(MARKER_FILE)
+            .exists()
+            && java_path.exists()
+        {
+            Some(java_path)
+        } else {
+            None
+        }
+    }
+
+    /// Installs the runtime `version` needs and returns the path of its `java` executable
+    #[tracing::instrument(skip(version))]
+    pub async fn install_for_version(
+        &self,
+        version: &Version,
+        downloader: &Downloader,
+    ) -> Result<PathBuf, JavaRuntimeError> {
+        let component = version
+            .java_version
+            .as_ref()
+            .map_or(DEFAULT_COMPONENT, |java_version| {
+                java_version.component.as_str()
+            });
+
+        self.install(component, downloader).await
+    }
+
+    /// Installs a component (e.g `java-runtime-gamma`) and returns the path of its `java`
+    /// executable.
+    ///
+    /// If the component is already installed from the same manifest it's left as is. Otherwise
+    /// only the files that are missing or changed are downloaded
+    #[tracing::instrument]
+    pub async fn install(
+        &self,
+        component: &str,
+        downloader: &Downloader,
+    ) -> Result<PathBuf, JavaRuntimeError> {
+        let index = self.index(downloader).await?;
+
+        let runtime = index
+            .get(&self.platform)
+            .ok_or_else(|| JavaRuntimeError::UnsupportedPlatform(self.platform.clone()))?
+            .get(component)
+            .and_then(|runtimes| runtimes.first())
+            .ok_or_else(|| JavaRuntimeError::NotFound {
+                component: component.to_string(),
+                platform: self.platform.clone(),
+            })?;
+
+        let directory = self.component_directory(component);
+        let marker = directory.join(MARKER_FILE);
+
+        if tokio::fs::read_to_string(&marker).await.ok().as_deref()
+            == Some(runtime.manifest.sha1.as_str())
+        {
+            debug!(
+                "{} {} is already installed",
+                component, runtime.version.name
+            );
+            return Ok(self.java_path(component));
+        }
+
+        debug!("Installing {} {}", component, runtime.version.name);
+        let manifest = downloader
+            .get_json::<RuntimeManifest>(&runtime.manifest.url)
+            .await?;
+
+        // the runtime isn't complete until the marker is written again
+        remove_if_exists(&marker).await?;
+        self.install_files(&manifest, &directory, downloader)
+            .await?;
+        tokio::fs::write(&marker, &runtime.manifest.sha1).await?;
+
+        debug!("Installed {} to {}", component, directory.display());
+        Ok(self.java_path(component))
+    }
+
+    async fn install_files(
+        &self,
+        manifest: &RuntimeManifest,
+        directory: &Path,
+        downloader: &Downloader,
+    ) -> Result<(), JavaRuntimeError> {
+        let mut entries = manifest
+            .files
+            .iter()
+            .map(|(name, file)| {
+                let path = runtime_path(directory, name)?;
+                if let RuntimeFile::Link { target } = file {
+                    check_link_target(directory, &path, target)?;
+                }
+
+                Ok((path, file))
+            })
+            .collect::<Result<Vec<_>, JavaRuntimeError>>()?;
+        // parent folders come before what's in them
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        tokio::fs::create_dir_all(directory).await?;
+
+        let mut jobs = vec![];
+        let mut compressed = vec![];
+
+        for (path, file) in &entries {
+            match file {
+                RuntimeFile::Directory => tokio::fs::create_dir_all(path).await?,
+                RuntimeFile::File { downloads, .. } => {
+                    if is_already_downloaded(path, &downloads.raw.sha1, downloads.raw.size).await {
+                        continue;
+                    }
+
+                    match &downloads.lzma {
+                        Some(lzma) => {
+                            let lzma_path = lzma_path(path);
+                            jobs.push(DownloadJob {
+                                url: lzma.url.clone(),
+                                path: lzma_path.clone(),
+                                sha1: Some(lzma.sha1.clone()),
+                                size: Some(lzma.size),
+                            });
+                            compressed.push((lzma_path, path.clone(), downloads.raw.sha1.clone()));
+                        }
+                        None => jobs.push(DownloadJob {
+                            url: downloads.raw.url.clone(),
+                            path: path.clone(),
+                            sha1: Some(downloads.raw.sha1.clone()),
+                            size: Some(downloads.raw.size),
+                        }),
+                    }
+                }
+                RuntimeFile::Link { .. } => {}
+            }
+        }
+
+        debug!(
+            "Downloading {} runtime files, {} of them compressed",
+            jobs.len(),
+            compressed.len()
+        );
+        let summary = downloader.start(jobs).download_task.await?;
+
+        if summary.status == DownloadStatus::Cancelled {
+            return Err(JavaRuntimeError::Cancelled);
+        }
+
+        if !summary.failed.is_empty() {
+            return Err(JavaRuntimeError::DownloadFailed(summary.failed.len()));
+        }
+
+        for (lzma_path, path, sha1) in compressed {
+            tokio::task::spawn_blocking(move || decompress(&lzma_path, &path, &sha1)).await??;
+        }
+
+        for (path, file) in &entries {
+            match file {
+                RuntimeFile::File {
+                    executable: true, ..
+                } => set_executable(path)?,
+                RuntimeFile::Link { target } => create_link(path, target)?,
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The platform name Mojang uses for the current OS and architecture, or `None` if Mojang doesn't
+/// publish runtimes for it (e.g linux on arm)
+pub fn current_platform() -> Option<&'static str> {
+    match (std::env::consts::OS, std::env::consts::ARCH) {
+        ("windows", "x86") => Some("windows-x86"),
+        ("windows", "x86_64") => Some("windows-x64"),
+        ("windows", "aarch64") => Some("windows-arm64"),
+        ("macos", "x86_64") => Some("mac-os"),
+        ("macos", "aarch64") => Some("mac-os-arm64"),
+        ("linux", "x86") => Some("linux-i386"),
+        ("linux", "x86_64") => Some("linux"),
+        _ => None,
+    }
+}
+
+/// Where an entry of the manifest goes, making sure it stays inside the runtime folder
+fn runtime_path(directory: &Path, name: &str) -> Result<PathBuf, JavaRuntimeError> {
+    let relative = Path::new(name);
+
+    if relative
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_)))
+    {
+        return Err(JavaRuntimeError::InvalidPath(name.to_string()));
+    }
+
+    Ok(directory.join(relative))
+}
+
+/// Makes sure a link at `path` to `target` points inside the runtime folder, as files could be
+/// written through it otherwise
+fn check_link_target(directory: &Path, path: &Path, target: &str) -> Result<(), JavaRuntimeError> {
+    let mut resolved = path.parent().unwrap_or(directory).to_path_buf();
+
+    for component in Path::new(target).components() {
+        let inside = match component {
+            Component::Normal(part) => {
+                resolved.push(part);
+                true
+            }
+            Component::CurDir => true,
+            Component::ParentDir => resolved.pop() && resolved.starts_with(directory),
+            // absolute targets
+            _ => false,
+        };
+
+        if !inside {
+            return Err(JavaRuntimeError::InvalidPath(format!(
+                "{} -> {}",
+                path.display(),
+                target
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Where the compressed version of a file is downloaded to, e.g `lib/modules.lzma`
+fn lzma_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".lzma");
+    path.with_file_name(file_name)
+}
+
+/// Decompresses `lzma_path` to `path` if it has the right SHA-1, and removes the compressed file
+fn decompress(lzma_path: &Path, path: &Path, sha1: &str) -> Result<(), JavaRuntimeError> {
+    trace!("Decompressing {}", lzma_path.display());
+    let temp_path = temp_download_path(path);
+    let mut output = HashingWriter {
+        inner: File::create(&temp_path)?,
+        hasher: Sha1::new(),
+    };
+
+    lzma_rs::lzma_decompress(&mut BufReader::new(File::open(lzma_path)?), &mut output)?;
+    output.flush()?;
+
+    let actual = format!("{:x}", output.hasher.finalize());
+    if !actual.eq_ignore_ascii_case(sha1) {
+        std::fs::remove_file(&temp_path)?;
+        return Err(JavaRuntimeError::Sha1Mismatch {
+            path: path.to_path_buf(),
+            expected: sha1.to_string(),
+            actual,
+        });
+    }
+
+    std::fs::rename(&temp_path, path)?;
+    std::fs::remove_file(lzma_path)?;
+    Ok(())
+}
+
+/// Hashes everything that is written through it
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha1,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(unix)]
+fn set_executable(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o111);
+    std::fs::set_permissions(path, permissions)
+}
+
+#[cfg(not(unix))]
+fn set_executable(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn create_link(path: &Path, target: &str) -> io::Result<()> {
+    trace!("Linking {} to {}", path.display(), target);
+
+    if std::fs::symlink_metadata(path).is_ok() {
+        std::fs::remove_file(path)?;
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    std::os::unix::fs::symlink(target, path)
+}
+
+// runtimes for windows don't have any links
+#[cfg(not(unix))]
+fn create_link(path: &Path, target: &str) -> io::Result<()> {
+    debug!("Not linking {} to {}", path.display(), target);
+    Ok(())
+}
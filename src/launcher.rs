@@ -1,15 +1,30 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{ExitStatus, Stdio};
 
+use crate::assets::structs::asset_index::AssetIndex;
 use crate::assets::structs::version::Version;
-use crate::errors::LauncherError;
+use crate::downloader::Downloader;
+use crate::errors::{JavaArgumentsError, LauncherError};
+use crate::java::discovery::{compatibility, inspect, JavaCompatibility};
+use crate::log4j::{Log4jMitigation, CONFIG_FILE_ARGUMENT, DISABLE_LOOKUPS_ARGUMENT};
+use crate::natives::extract_natives;
 use crate::parser::JavaArguments;
+use crate::resolver::VersionResolver;
 use crate::{assets, parser::GameArguments};
 use tokio::fs;
 use tokio::io::BufReader;
 use tokio::process::{ChildStderr, ChildStdout, Command};
 use tokio::task::JoinHandle;
-use tracing::{debug, trace};
+use tracing::{debug, info, trace, warn};
+
+/// The JVM arguments used for versions before 1.13, which don't have any in their manifest
+const LEGACY_JVM_ARGUMENTS: &[&str] = &[
+    "-Djava.library.path=${natives_directory}",
+    "-Dminecraft.launcher.brand=${launcher_name}",
+    "-Dminecraft.launcher.version=${launcher_version}",
+    "-cp",
+    "${classpath}",
+];
 
 #[derive(Default, Debug, Clone)]
 pub struct AuthenticationDetails {
@@ -27,6 +42,21 @@ pub struct CustomResolution {
     pub height: i32,
 }
 
+/// What to do when the java is too old for the version, e.g java 8 for 1.18.
+///
+/// Java newer than 8 for versions that need 8 is only ever warned about, as just the ones before
+/// 1.13 break on it
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JavaVersionCheck {
+    /// refuse to launch with [`LauncherError::IncompatibleJava`]
+    #[default]
+    Refuse,
+    /// only log a warning
+    Warn,
+    /// don't check the java version
+    Skip,
+}
+
 #[derive(Default, Clone, Debug)]
 pub struct RamSize {
     pub min: String,
@@ -53,6 +83,8 @@ pub struct Launcher {
     pub assets_directory: PathBuf,
     /// the libraries directory, this is the root of the libraries folder
     pub libraries_directory: PathBuf,
+    /// where to extract the natives to, `versions/<id>/natives` if not set
+    pub natives_directory: Option<PathBuf>,
     /// the path to <version>.json
     pub version_manifest_path: PathBuf,
     /// is this version a snapshot
@@ -65,30 +97,85 @@ pub struct Launcher {
     pub ram_size: RamSize,
     /// the path to javaw.exe
     pub java_path: PathBuf,
+    /// what to do when `java_path` can't run the version
+    pub java_version_check: JavaVersionCheck,
     /// the launcher name (e.g glowsquid)
     pub launcher_name: String,
+    /// don't make any network requests, everything has to be downloaded already
+    pub offline: bool,
+    /// don't protect versions that ship a vulnerable log4j (1.7 to 1.18.0) from Log4Shell
+    pub skip_log4j_mitigation: bool,
 }
 
 impl Launcher {
+    /// Launches the game. If `version_manifest` is `None`, it is read from `version_manifest_path`
+    /// and merged with every version it inherits from
     #[tracing::instrument]
     pub async fn launch(
         &self,
         version_manifest: Option<Version>,
-        client: reqwest::Client,
+        downloader: &Downloader,
     ) -> Result<GameOutput, LauncherError> {
         trace!("Launching minecraft");
 
         let version_manifest = match version_manifest {
             Some(manifest) => manifest,
-            None => serde_json::from_str(
-                &fs::read_to_string(self.version_manifest_path.clone()).await?,
-            )?,
+            None => {
+                let manifest = serde_json::from_str(
+                    &fs::read_to_string(self.version_manifest_path.clone()).await?,
+                )?;
+
+                // versions/<id>/<id>.json
+                let versions_directory = self
+                    .version_manifest_path
+                    .parent()
+                    .and_then(Path::parent)
+                    .ok_or(LauncherError::NoVersionsDirectory)?;
+
+                let (manifest, replaced_libraries) =
+                    VersionResolver::new(versions_directory.to_path_buf())
+                        .offline(self.offline)
+                        .resolve_version_with_replaced(manifest, downloader)
+                        .await?;
+
+                for library in &replaced_libraries {
+                    info!(
+                        "Using library {} instead of {} from a parent version",
+                        library.kept, library.replaced
+                    );
+                }
+
+                manifest
+            }
         };
 
-        let game_args = self.parse_game_arguments(&version_manifest)?;
+        self.check_java_version(&version_manifest).await?;
+
+        let natives = extract_natives(
+            &version_manifest,
+            &self.libraries_directory,
+            &self.natives_directory(),
+        )
+        .await?;
+
+        // the natives end up somewhere else if the old ones are still in use
+        let launcher = Self {
+            natives_directory: Some(natives.directory),
+            ..self.clone()
+        };
+
+        launcher.copy_legacy_assets().await?;
+
+        let game_args = launcher.parse_game_arguments(&version_manifest)?;
         debug!("Game arguments: {:?}", &game_args);
 
-        let java_args = self.parse_java_arguments(&version_manifest, client).await?;
+        let mut java_args = launcher
+            .parse_java_arguments(&version_manifest, downloader)
+            .await?;
+        let logging_args = launcher
+            .logging_arguments(&version_manifest, downloader)
+            .await?;
+        java_args.extend(logging_args);
 
         let main_class = version_manifest
             .main_class
@@ -128,29 +215,207 @@ impl Launcher {
         })
     }
 
+    /// The folder the natives are extracted to, `versions/<id>/natives` (next to the version
+    /// manifest) unless another one is set
+    pub fn natives_directory(&self) -> PathBuf {
+        self.natives_directory
+            .clone()
+            .unwrap_or_else(|| self.version_manifest_path.with_file_name("natives"))
+    }
+
+    /// The asset index of the version, `<assets_directory>/indexes/<version_name>.json`
+    pub fn asset_index_path(&self) -> PathBuf {
+        self.assets_directory
+            .join("indexes")
+            .join(format!("{}.json", self.version_name))
+    }
+
+    /// The folder the game looks for assets in by name (`${game_assets}`). Versions with a legacy
+    /// asset index (before 1.7.3) use `assets/virtual/legacy` or `resources`, newer ones the assets
+    /// folder
+    pub fn game_assets_directory(&self) -> Result<PathBuf, JavaArgumentsError> {
+        let path = self.asset_index_path();
+        let json = std::fs::read_to_string(&path)
+            .map_err(|_| JavaArgumentsError::MissingFile(path.clone()))?;
+        let asset_index = serde_json::from_str::<AssetIndex>(&json)?;
+
+        Ok(asset_index
+            .legacy_assets_directory(&self.assets_directory, &self.game_directory)
+            .unwrap_or_else(|| self.assets_directory.clone()))
+    }
+
+    /// Copies the assets to where versions with a legacy asset index look for them by name. The
+    /// asset index is optional here, as newer versions don't need it to launch
+    #[tracing::instrument]
+    async fn copy_legacy_assets(&self) -> Result<(), LauncherError> {
+        let path = self.asset_index_path();
+        if !path.exists() {
+            debug!("No asset index at {}", path.display());
+            return Ok(());
+        }
+
+        let asset_index = serde_json::from_str::<AssetIndex>(&fs::read_to_string(&path).await?)?;
+        asset_index
+            .copy_legacy_assets(&self.assets_directory, &self.game_directory)
+            .await?;
+        Ok(())
+    }
+
+    /// Checks that `java_path` can run the version, if the version says which java it needs
+    #[tracing::instrument]
+    async fn check_java_version(&self, version_manifest: &Version) -> Result<(), LauncherError> {
+        if self.java_version_check == JavaVersionCheck::Skip {
+            return Ok(());
+        }
+
+        let required = match version_manifest
+            .java_version
+            .as_ref()
+            .and_then(|java_version| u32::try_from(java_version.major_version).ok())
+        {
+            Some(required) => required,
+            None => return Ok(()),
+        };
+
+        let found = match inspect(&self.java_path).await {
+            Ok(installation) => installation.major_version,
+            Err(err) if self.java_version_check == JavaVersionCheck::Warn => {
+                warn!("Couldn't check the java version: {}", err);
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        debug!("Java {} found, {} required", found, required);
+        match compatibility(required, found) {
+            JavaCompatibility::Compatible => Ok(()),
+            JavaCompatibility::Newer => {
+                warn!(
+                    "Java {} at {} is newer than java {}, which this version needs. Versions before 1.13 don't run on it",
+                    found,
+                    self.java_path.display(),
+                    required
+                );
+                Ok(())
+            }
+            JavaCompatibility::TooOld if self.java_version_check == JavaVersionCheck::Warn => {
+                warn!(
+                    "Java {} at {} can't run this version, it needs java {}",
+                    found,
+                    self.java_path.display(),
+                    required
+                );
+                Ok(())
+            }
+            JavaCompatibility::TooOld => Err(LauncherError::IncompatibleJava {
+                path: self.java_path.clone(),
+                required,
+                found,
+            }),
+        }
+    }
+
+    /// The JVM arguments for logging: the logging config of the version and, unless it is turned
+    /// off, the Log4Shell mitigation for it.
+    ///
+    /// Versions that need a patched config get it instead of their own logging config. It's
+    /// downloaded if it isn't there yet
+    #[tracing::instrument]
+    async fn logging_arguments(
+        &self,
+        version_manifest: &Version,
+        downloader: &Downloader,
+    ) -> Result<Vec<String>, LauncherError> {
+        let mitigation = if self.skip_log4j_mitigation {
+            None
+        } else {
+            Log4jMitigation::for_version(version_manifest)
+        };
+
+        let config = match mitigation {
+            Some(Log4jMitigation::PatchedConfig(config)) => config,
+            Some(Log4jMitigation::DisableLookups) => {
+                debug!("Disabling log4j lookups");
+                let mut args = Vec::from_iter(self.logging_argument(version_manifest).await);
+                args.push(DISABLE_LOOKUPS_ARGUMENT.to_string());
+                return Ok(args);
+            }
+            None => {
+                return Ok(Vec::from_iter(
+                    self.logging_argument(version_manifest).await,
+                ))
+            }
+        };
+
+        debug!("Using the patched log4j config {}", config.id);
+        let path = config.path(&self.assets_directory);
+
+        if !path.exists() {
+            if self.offline {
+                return Err(LauncherError::MissingLog4jConfig(path));
+            }
+
+            config.download(&self.assets_directory, downloader).await?;
+        }
+
+        let path = fs::canonicalize(path).await?;
+
+        Ok(vec![match &version_manifest.logging {
+            Some(logging) => logging.client.jvm_argument(&path),
+            None => CONFIG_FILE_ARGUMENT.replace("${path}", &path.to_string_lossy()),
+        }])
+    }
+
+    /// The JVM argument that points log4j to the logging config of the version, if it has one and
+    /// it was downloaded
+    #[tracing::instrument]
+    async fn logging_argument(&self, version_manifest: &Version) -> Option<String> {
+        let client = &version_manifest.logging.as_ref()?.client;
+        let config_path = client.config_path(&self.assets_directory);
+
+        match fs::canonicalize(&config_path).await {
+            Ok(path) => Some(client.jvm_argument(&path)),
+            Err(err) => {
+                warn!(
+                    "Couldn't find the logging config {}, launching without it: {}",
+                    config_path.display(),
+                    err
+                );
+                None
+            }
+        }
+    }
+
     #[tracing::instrument]
     async fn parse_java_arguments(
         &self,
         version_manifest: &Version,
-        client: reqwest::Client,
+        downloader: &Downloader,
     ) -> Result<Vec<String>, LauncherError> {
         let mut args: Vec<String> = vec![];
 
-        for arg in version_manifest
+        let jvm_arguments = match version_manifest
             .arguments
             .as_ref()
-            .ok_or(LauncherError::NoArgs)?
-            .jvm
-            .as_ref()
-            .ok_or(LauncherError::NoArgs)?
+            .and_then(|arguments| arguments.jvm.as_ref())
         {
+            Some(jvm_arguments) => jvm_arguments,
+            None if version_manifest.minecraft_arguments.is_some() => {
+                return self
+                    .legacy_java_arguments(version_manifest, downloader)
+                    .await
+            }
+            None => return Err(LauncherError::NoArgs),
+        };
+
+        for arg in jvm_arguments {
             let formatted_arg = match arg {
                 assets::structs::version::JvmElement::JvmClass(argument) => {
                     JavaArguments::parse_class_argument(
                         self,
                         version_manifest,
                         argument,
-                        client.clone(),
+                        downloader,
                     )
                     .await?
                 }
@@ -159,7 +424,7 @@ impl Launcher {
                         self,
                         version_manifest,
                         argument.to_string(),
-                        client.clone(),
+                        downloader,
                     )
                     .await?,
                 ),
@@ -173,6 +438,30 @@ impl Launcher {
         Ok(args)
     }
 
+    /// Versions before 1.13 leave the JVM arguments to the launcher
+    #[tracing::instrument]
+    async fn legacy_java_arguments(
+        &self,
+        version_manifest: &Version,
+        downloader: &Downloader,
+    ) -> Result<Vec<String>, LauncherError> {
+        let mut args: Vec<String> = vec![];
+
+        for argument in LEGACY_JVM_ARGUMENTS {
+            args.push(
+                JavaArguments::parse_string_argument(
+                    self,
+                    version_manifest,
+                    argument.to_string(),
+                    downloader,
+                )
+                .await?,
+            );
+        }
+
+        Ok(args)
+    }
+
     #[tracing::instrument]
     fn parse_game_arguments(
         &self,
@@ -180,14 +469,21 @@ impl Launcher {
     ) -> Result<Vec<String>, LauncherError> {
         let mut args: Vec<String> = vec![];
 
-        for arg in version_manifest
+        let game_arguments = match version_manifest
             .arguments
             .as_ref()
-            .ok_or(LauncherError::NoArgs)?
-            .game
-            .as_ref()
-            .ok_or(LauncherError::NoArgs)?
+            .and_then(|arguments| arguments.game.as_ref())
         {
+            Some(game_arguments) => game_arguments,
+            None => match &version_manifest.minecraft_arguments {
+                Some(minecraft_arguments) => {
+                    return self.parse_legacy_game_arguments(minecraft_arguments)
+                }
+                None => return Err(LauncherError::NoArgs),
+            },
+        };
+
+        for arg in game_arguments {
             let formatted_arg = match arg {
                 assets::structs::version::GameElement::GameClass(argument) => {
                     GameArguments::parse_class_argument(self, argument)?
@@ -205,4 +501,93 @@ impl Launcher {
 
         Ok(args)
     }
+
+    /// Parses the space separated `minecraftArguments` of versions before 1.13
+    #[tracing::instrument]
+    fn parse_legacy_game_arguments(
+        &self,
+        minecraft_arguments: &str,
+    ) -> Result<Vec<String>, LauncherError> {
+        let mut args = minecraft_arguments
+            .split_whitespace()
+            .map(|argument| GameArguments::parse_string_argument(self, argument.to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // there are no rules to add these with
+        if let Some(resolution) = &self.custom_resolution {
+            args.extend([
+                "--width".to_string(),
+                resolution.width.to_string(),
+                "--height".to_string(),
+                resolution.height.to_string(),
+            ]);
+        }
+
+        Ok(args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log4j::PATCHED_CONFIG_1_12;
+
+    fn version_1_12_2() -> Version {
+        serde_json::from_str(include_str!("../tests/fixtures/1.12.2.json")).unwrap()
+    }
+
+    #[test]
+    fn parses_the_minecraft_arguments_of_1_12_2() {
+        let game_directory = tempfile::tempdir().unwrap();
+        let assets_directory = game_directory.path().join("assets");
+        std::fs::create_dir(&assets_directory).unwrap();
+
+        let launcher = Launcher {
+            authentication_details: AuthenticationDetails {
+                username: "Steve".to_string(),
+                uuid: "069a79f444e94726a5befca90e38aaf5".to_string(),
+                access_token: "token".to_string(),
+                ..AuthenticationDetails::default()
+            },
+            game_directory: game_directory.path().to_path_buf(),
+            assets_directory: assets_directory.clone(),
+            version_name: "1.12.2".to_string(),
+            ..Launcher::default()
+        };
+
+        let game_directory = dunce::canonicalize(game_directory.path()).unwrap();
+        let assets_directory = dunce::canonicalize(assets_directory).unwrap();
+
+        assert_eq!(
+            launcher.parse_game_arguments(&version_1_12_2()).unwrap(),
+            [
+                "--username",
+                "Steve",
+                "--version",
+                "1.12.2",
+                "--gameDir",
+                game_directory.to_str().unwrap(),
+                "--assetsDir",
+                assets_directory.to_str().unwrap(),
+                "--assetIndex",
+                "1.12.2",
+                "--uuid",
+                "069a79f444e94726a5befca90e38aaf5",
+                "--accessToken",
+                "token",
+                "--userType",
+                "msa",
+                "--versionType",
+                "release",
+            ]
+        );
+    }
+
+    #[test]
+    fn uses_the_patched_log4j_config_for_1_12_2() {
+        assert_eq!(
+            Log4jMitigation::for_version(&version_1_12_2()),
+            Some(Log4jMitigation::PatchedConfig(PATCHED_CONFIG_1_12))
+        );
+    }
 }
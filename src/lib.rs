@@ -1,5 +1,11 @@
 pub mod assets;
+pub mod downloader;
+pub mod java;
 pub mod launcher;
+pub mod log4j;
+pub mod maven;
+pub mod natives;
 pub mod parser;
+pub mod resolver;
 pub mod util;
 pub mod errors;
@@ -0,0 +1,125 @@
+use std::path::{Path, PathBuf};
+
+use tracing::{debug, trace};
+
+use crate::assets::structs::version::Version;
+use crate::downloader::{DownloadJob, Downloader};
+use crate::errors::DownloadError;
+use crate::util::MavenIdentifier;
+
+/// The JVM argument that stops log4j 2.10 and newer from evaluating lookups in log messages
+pub const DISABLE_LOOKUPS_ARGUMENT: &str = "-Dlog4j2.formatMsgNoLookups=true";
+
+/// The JVM argument used to point log4j to a patched config, for versions without a logging config
+pub const CONFIG_FILE_ARGUMENT: &str = "-Dlog4j.configurationFile=${path}";
+
+/// The patched config Mojang published for 1.7 to 1.11.2, which use log4j 2.0-beta9
+pub const PATCHED_CONFIG_1_7: PatchedConfig = PatchedConfig {
+    id: "log4j2_17-111.xml",
+    url: "https://launcher.mojang.com/v1/objects/4bb89a97a66f350bc9f73b3ca8509632682aea2e/log4j2_17-111.xml",
+    sha1: "4bb89a97a66f350bc9f73b3ca8509632682aea2e",
+};
+
+/// The patched config Mojang published for 1.12 to 1.16.5, which use log4j 2.8.1
+pub const PATCHED_CONFIG_1_12: PatchedConfig = PatchedConfig {
+    id: "log4j2_112-116.xml",
+    url: "https://launcher.mojang.com/v1/objects/02937d122c86ce73319ef9975b58896fc1b491d1/log4j2_112-116.xml",
+    sha1: "02937d122c86ce73319ef9975b58896fc1b491d1",
+};
+
+/// How to protect a version from Log4Shell (CVE-2021-44228)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Log4jMitigation {
+    /// log4j 2.10 to 2.14.1 (1.17 to 1.18.0), which can turn lookups off with
+    /// [`DISABLE_LOOKUPS_ARGUMENT`]
+    DisableLookups,
+    /// log4j before 2.10 (1.7 to 1.16.5) doesn't have that option, so the logging config has to be
+    /// replaced with one that doesn't use lookups
+    PatchedConfig(PatchedConfig),
+}
+
+/// A log4j config without lookups that replaces the logging config of the version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PatchedConfig {
+    /// the file name, e.g `log4j2_112-116.xml`
+    pub id: &'static str,
+    pub url: &'static str,
+    pub sha1: &'static str,
+}
+
+impl Log4jMitigation {
+    /// Finds out how to mitigate Log4Shell for `version`, from the version of `log4j-core` in its
+    /// libraries. Returns `None` if the version doesn't use log4j or uses a fixed one (2.15.0 and
+    /// newer, so 1.18.1 and up)
+    #[tracing::instrument(skip(version))]
+    pub fn for_version(version: &Version) -> Option<Self> {
+        let log4j_version = version
+            .libraries
+            .iter()
+            .flatten()
+            .filter(|library| {
+                library
+                    .rules
+                    .as_ref()
+                    .is_none_or(Version::check_library_rules)
+            })
+            .filter_map(|library| library.name.parse::<MavenIdentifier>().ok())
+            .find(|identifier| {
+                identifier.group_id == "org.apache.logging.log4j"
+                    && identifier.artifact_id == "log4j-core"
+            })?
+            .version;
+
+        trace!("Version {:?} uses log4j {}", version.id, log4j_version);
+
+        let mitigation = match parse_version(&log4j_version) {
+            (2, 15..) => None,
+            (2, 10..) => Some(Self::DisableLookups),
+            // 2.0-beta9, used before 1.12
+            (2, 0) => Some(Self::PatchedConfig(PATCHED_CONFIG_1_7)),
+            (2, _) => Some(Self::PatchedConfig(PATCHED_CONFIG_1_12)),
+            // log4j 1 isn't affected, and neither is anything newer than 2
+            _ => None,
+        };
+
+        debug!("log4j {} needs mitigation {:?}", log4j_version, mitigation);
+        mitigation
+    }
+}
+
+impl PatchedConfig {
+    /// Where the patched config is saved, `<assets_directory>/log_configs/<id>`
+    pub fn path(&self, assets_directory: &Path) -> PathBuf {
+        assets_directory.join("log_configs").join(self.id)
+    }
+
+    /// Downloads the patched config to `<assets_directory>/log_configs/<id>`
+    #[tracing::instrument]
+    pub async fn download(
+        &self,
+        assets_directory: &Path,
+        downloader: &Downloader,
+    ) -> Result<(), DownloadError> {
+        downloader
+            .download(DownloadJob {
+                url: self.url.to_string(),
+                path: self.path(assets_directory),
+                sha1: Some(self.sha1.to_string()),
+                size: None,
+            })
+            .await?
+    }
+}
+
+/// Parses the major and minor version of a log4j version like `2.8.1` or `2.0-beta9`. Missing or
+/// invalid parts are 0
+fn parse_version(version: &str) -> (u32, u32) {
+    let mut parts = version
+        .split(['-', '+'])
+        .next()
+        .unwrap_or_default()
+        .split('.')
+        .map(|part| part.parse().unwrap_or(0));
+
+    (parts.next().unwrap_or(0), parts.next().unwrap_or(0))
+}
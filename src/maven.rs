@@ -0,0 +1,468 @@
+use reqwest::header::CONTENT_LENGTH;
+use reqwest::{Method, StatusCode};
+use serde::Deserialize;
+use tracing::{debug, trace, warn};
+
+use crate::downloader::Downloader;
+use crate::errors::MavenError;
+use crate::util::MavenIdentifier;
+
+/// The suffix of versions that are resolved to a timestamped build
+const SNAPSHOT_SUFFIX: &str = "-SNAPSHOT";
+
+/// An ordered list of maven repositories. Artifacts are looked up in each repository in order until
+/// one of them has it
+#[derive(Debug, Clone, Default)]
+pub struct MavenRepositories {
+    /// the base URLs of the repositories, e.g `https://maven.fabricmc.net/`
+    pub urls: Vec<String>,
+}
+
+/// An artifact that was found in a repository
+#[derive(Debug, Clone)]
+pub struct ResolvedArtifact {
+    /// the identifier with dynamic versions (`latest`/`release`) replaced by the actual version.
+    /// Snapshot versions are kept, so the artifact is saved to the same place as it'd be without
+    /// resolving it
+    pub identifier: MavenIdentifier,
+    /// the base URL of the repository the artifact was found in
+    pub repository: String,
+    /// where to download the artifact from
+    pub url: String,
+    /// the size of the artifact in bytes, if the repository said
+    pub size: Option<u64>,
+}
+
+/// `maven-metadata.xml`
+#[derive(Debug, Deserialize)]
+struct MavenMetadata {
+    versioning: Option<Versioning>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Versioning {
+    latest: Option<String>,
+    release: Option<String>,
+    versions: Option<Versions>,
+    snapshot: Option<Snapshot>,
+    snapshot_versions: Option<SnapshotVersions>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Versions {
+    #[serde(rename = "version", default)]
+    versions: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Snapshot {
+    timestamp: Option<String>,
+    build_number: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SnapshotVersions {
+    #[serde(rename = "snapshotVersion", default)]
+    snapshot_versions: Vec<SnapshotVersion>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SnapshotVersion {
+    classifier: Option<String>,
+    extension: Option<String>,
+    value: String,
+}
+
+impl MavenRepositories {
+    pub fn new(urls: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            urls: urls.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Lists every version of an artifact, from the first repository that has it
+    #[tracing::instrument]
+    pub async fn versions(
+        &self,
+        group_id: &str,
+        artifact_id: &str,
+        downloader: &Downloader,
+    ) -> Result<Vec<String>, MavenError> {
+        let mut last_error = None;
+
+        for repository in &self.urls {
+            match Self::artifact_metadata(repository, group_id, artifact_id, downloader).await {
+                Ok(versioning) => {
+                    return Ok(versioning
+                        .versions
+                        .map(|versions| versions.versions)
+                        .unwrap_or_default())
+                }
+                Err(err) if is_missing(&err) => debug!(
+                    "{}:{} isn't in {}: {}",
+                    group_id, artifact_id, repository, err
+                ),
+                Err(err) => {
+                    warn!(
+                        "Couldn't get the versions of {}:{} from {}: {}",
+                        group_id, artifact_id, repository, err
+                    );
+                    last_error = Some(err);
+                }
+            }
+        }
+
+        Err(last_error
+            .unwrap_or_else(|| MavenError::NotFound(format!("{}:{}", group_id, artifact_id))))
+    }
+
+    /// Finds the artifact in the first repository that has it.
+    ///
+    /// `SNAPSHOT` versions are resolved to the latest timestamped build, and `latest` or `release`
+    /// as the version are resolved to the latest (release) version of the artifact
+    #[tracing::instrument]
+    pub async fn resolve(
+        &self,
+        identifier: &MavenIdentifier,
+        downloader: &Downloader,
+    ) -> Result<ResolvedArtifact, MavenError> {
+        let mut last_error = None;
+
+        for repository in &self.urls {
+            match Self::resolve_in(repository, identifier, downloader).await {
+                Ok(artifact) => return Ok(artifact),
+                Err(err) if is_missing(&err) => {
+                    debug!("{} isn't in {}: {}", identifier, repository, err)
+                }
+                Err(err) => {
+                    warn!(
+                        "Couldn't look for {} in {}: {}",
+                        identifier, repository, err
+                    );
+                    last_error = Some(err);
+                }
+            }
+        }
+
+        // a repository that failed might have had it, so that's more useful than "not found"
+        Err(last_error.unwrap_or_else(|| MavenError::NotFound(identifier.to_string())))
+    }
+
+    async fn resolve_in(
+        repository: &str,
+        identifier: &MavenIdentifier,
+        downloader: &Downloader,
+    ) -> Result<ResolvedArtifact, MavenError> {
+        let mut identifier = identifier.clone();
+
+        if identifier.version == "latest" || identifier.version == "release" {
+            let versioning = Self::artifact_metadata(
+                repository,
+                &identifier.group_id,
+                &identifier.artifact_id,
+                downloader,
+            )
+            .await?;
+
+            identifier.version = versioning
+                .dynamic_version(&identifier.version)
+                .ok_or_else(|| MavenError::NoVersions(identifier.to_string()))?;
+
+            debug!("Resolved {} to version {}", identifier, identifier.version);
+        }
+
+        let url = if identifier.version.ends_with(SNAPSHOT_SUFFIX) {
+            let snapshot_version =
+                Self::snapshot_version(repository, &identifier, downloader).await?;
+            debug!("Resolved {} to build {}", identifier, snapshot_version);
+
+            let snapshot = MavenIdentifier {
+                version: snapshot_version,
+                ..identifier.clone()
+            };
+
+            // the build is still in the folder of the snapshot version
+            format!(
+                "{}/{}/{}/{}/{}",
+                repository.trim_end_matches('/'),
+                identifier.group_id.replace('.', "/"),
+                identifier.artifact_id,
+                identifier.version,
+                snapshot.file_name()
+            )
+        } else {
+            identifier.to_url(repository)
+        };
+
+        let response = downloader.request(Method::HEAD, &url).await?;
+        trace!("Found {} at {}", identifier, url);
+
+        Ok(ResolvedArtifact {
+            identifier,
+            repository: repository.to_string(),
+            url,
+            // `content_length()` is the size of the body, which is always empty for HEAD requests
+            size: response
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|length| length.to_str().ok()?.parse().ok()),
+        })
+    }
+
+    /// Gets the timestamped version of the latest build of a snapshot, e.g `1.0-20220101.123456-3`
+    /// for `1.0-SNAPSHOT`
+    async fn snapshot_version(
+        repository: &str,
+        identifier: &MavenIdentifier,
+        downloader: &Downloader,
+    ) -> Result<String, MavenError> {
+        let url = format!(
+            "{}/{}/{}/{}/maven-metadata.xml",
+            repository.trim_end_matches('/'),
+            identifier.group_id.replace('.', "/"),
+            identifier.artifact_id,
+            identifier.version
+        );
+
+        Ok(Self::metadata(&url, downloader)
+            .await?
+            .snapshot_version(identifier))
+    }
+
+    async fn artifact_metadata(
+        repository: &str,
+        group_id: &str,
+        artifact_id: &str,
+        downloader: &Downloader,
+    ) -> Result<Versioning, MavenError> {
+        let url = format!(
+            "{}/{}/{}/maven-metadata.xml",
+            repository.trim_end_matches('/'),
+            group_id.replace('.', "/"),
+            artifact_id
+        );
+
+        Self::metadata(&url, downloader).await
+    }
+
+    async fn metadata(url: &str, downloader: &Downloader) -> Result<Versioning, MavenError> {
+        trace!("Getting maven metadata from {}", url);
+        let xml = downloader.request(Method::GET, url).await?.text().await?;
+
+        Versioning::parse(&xml, url)
+    }
+}
+
+impl Versioning {
+    /// Parses the `versioning` of a `maven-metadata.xml` that was downloaded from `url`
+    fn parse(xml: &str, url: &str) -> Result<Self, MavenError> {
+        quick_xml::de::from_str::<MavenMetadata>(xml)?
+            .versioning
+            .ok_or_else(|| MavenError::NoVersions(url.to_string()))
+    }
+
+    /// The version `latest` or `release` stands for. Falls back to the last listed version if the
+    /// metadata doesn't say
+    fn dynamic_version(self, version: &str) -> Option<String> {
+        let dynamic = if version == "latest" {
+            self.latest
+        } else {
+            self.release
+        };
+
+        dynamic.or_else(|| self.versions?.versions.pop())
+    }
+
+    /// The timestamped version of the latest build of the snapshot `identifier`, from the metadata
+    /// of the snapshot version
+    fn snapshot_version(self, identifier: &MavenIdentifier) -> String {
+        // newer repositories list the exact version of every file
+        let listed = self
+            .snapshot_versions
+            .into_iter()
+            .flat_map(|snapshot_versions| snapshot_versions.snapshot_versions)
+            .find(|snapshot_version| {
+                snapshot_version.classifier == identifier.classifier
+                    && snapshot_version.extension.as_deref() == Some(&identifier.extension)
+            });
+
+        if let Some(snapshot_version) = listed {
+            return snapshot_version.value;
+        }
+
+        match self.snapshot {
+            Some(Snapshot {
+                timestamp: Some(timestamp),
+                build_number: Some(build_number),
+            }) => format!(
+                "{}-{}-{}",
+                identifier.version.trim_end_matches(SNAPSHOT_SUFFIX),
+                timestamp,
+                build_number
+            ),
+            // published with the local repository layout, so the files aren't timestamped
+            _ => identifier.version.clone(),
+        }
+    }
+}
+
+/// Whether `err` just means the repository doesn't have the artifact, so the next one should be
+/// tried. Anything else (e.g a network error or broken metadata) is a real error
+fn is_missing(err: &MavenError) -> bool {
+    match err {
+        MavenError::RequestError(err) => err.status() == Some(StatusCode::NOT_FOUND),
+        MavenError::NotFound(_) | MavenError::NoVersions(_) => true,
+        MavenError::XmlError(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RELEASES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<metadata>
+  <groupId>net.fabricmc</groupId>
+  <artifactId>fabric-loader</artifactId>
+  <versioning>
+    <latest>0.14.9-beta</latest>
+    <release>0.14.8</release>
+    <versions>
+      <version>0.14.7</version>
+      <version>0.14.8</version>
+      <version>0.14.9-beta</version>
+    </versions>
+    <lastUpdated>20220801000000</lastUpdated>
+  </versioning>
+</metadata>"#;
+
+    const SNAPSHOT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<metadata modelVersion="1.1.0">
+  <groupId>org.example</groupId>
+  <artifactId>natives</artifactId>
+  <version>1.0-SNAPSHOT</version>
+  <versioning>
+    <snapshot>
+      <timestamp>20220101.123456</timestamp>
+      <buildNumber>3</buildNumber>
+    </snapshot>
+    <lastUpdated>20220101123456</lastUpdated>
+    <snapshotVersions>
+      <snapshotVersion>
+        <extension>jar</extension>
+        <value>1.0-20220101.123456-3</value>
+        <updated>20220101123456</updated>
+      </snapshotVersion>
+      <snapshotVersion>
+        <classifier>natives-linux</classifier>
+        <extension>jar</extension>
+        <value>1.0-20211231.101010-2</value>
+        <updated>20211231101010</updated>
+      </snapshotVersion>
+      <snapshotVersion>
+        <extension>pom</extension>
+        <value>1.0-20220101.123456-3</value>
+        <updated>20220101123456</updated>
+      </snapshotVersion>
+    </snapshotVersions>
+  </versioning>
+</metadata>"#;
+
+    fn parse(xml: &str) -> Versioning {
+        Versioning::parse(xml, "https://maven.example.org/maven-metadata.xml").unwrap()
+    }
+
+    fn identifier(identifier: &str) -> MavenIdentifier {
+        identifier.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_metadata() {
+        let versioning = parse(RELEASES);
+
+        assert_eq!(versioning.latest.as_deref(), Some("0.14.9-beta"));
+        assert_eq!(versioning.release.as_deref(), Some("0.14.8"));
+        assert_eq!(
+            versioning.versions.unwrap().versions,
+            ["0.14.7", "0.14.8", "0.14.9-beta"]
+        );
+    }
+
+    #[test]
+    fn fails_on_metadata_without_versioning() {
+        assert!(matches!(
+            Versioning::parse("<metadata></metadata>", "maven-metadata.xml"),
+            Err(MavenError::NoVersions(_))
+        ));
+        assert!(matches!(
+            Versioning::parse("<metadata><versioning>", "maven-metadata.xml"),
+            Err(MavenError::XmlError(_))
+        ));
+    }
+
+    #[test]
+    fn resolves_latest_and_release() {
+        assert_eq!(
+            parse(RELEASES).dynamic_version("latest").as_deref(),
+            Some("0.14.9-beta")
+        );
+        assert_eq!(
+            parse(RELEASES).dynamic_version("release").as_deref(),
+            Some("0.14.8")
+        );
+    }
+
+    #[test]
+    fn resolves_to_the_last_version_without_latest_or_release() {
+        let versioning = parse(
+            "<metadata><versioning><versions><version>1.0</version><version>1.1</version>\
+             </versions></versioning></metadata>",
+        );
+
+        assert_eq!(
+            versioning.dynamic_version("release").as_deref(),
+            Some("1.1")
+        );
+    }
+
+    #[test]
+    fn resolves_snapshots_from_the_listed_versions() {
+        assert_eq!(
+            parse(SNAPSHOT).snapshot_version(&identifier("org.example:natives:1.0-SNAPSHOT")),
+            "1.0-20220101.123456-3"
+        );
+        assert_eq!(
+            parse(SNAPSHOT).snapshot_version(&identifier(
+                "org.example:natives:1.0-SNAPSHOT:natives-linux"
+            )),
+            "1.0-20211231.101010-2"
+        );
+    }
+
+    #[test]
+    fn resolves_snapshots_from_the_latest_build() {
+        let versioning = parse(
+            "<metadata><versioning><snapshot><timestamp>20220101.123456</timestamp>\
+             <buildNumber>3</buildNumber></snapshot></versioning></metadata>",
+        );
+
+        assert_eq!(
+            versioning.snapshot_version(&identifier(
+                "org.example:natives:1.0-SNAPSHOT:natives-windows"
+            )),
+            "1.0-20220101.123456-3"
+        );
+    }
+
+    #[test]
+    fn keeps_snapshots_without_builds() {
+        let versioning = parse("<metadata><versioning></versioning></metadata>");
+
+        assert_eq!(
+            versioning.snapshot_version(&identifier("org.example:natives:1.0-SNAPSHOT")),
+            "1.0-SNAPSHOT"
+        );
+    }
+}
@@ -0,0 +1,333 @@
+use std::fs::{create_dir_all, read_dir, remove_dir_all, rename, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use sha1::{Digest, Sha1};
+use tracing::{debug, trace};
+use zip::ZipArchive;
+
+use crate::assets::structs::version::Version;
+use crate::errors::NativesError;
+
+/// The file in a natives folder that records which natives were extracted into it
+const MARKER_FILE: &str = ".copper-natives";
+
+/// How old a staging folder has to be before it's treated as left behind by a crashed launch
+const STALE_STAGING_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Where the natives of a version were extracted to
+#[derive(Debug, Clone)]
+pub struct ExtractedNatives {
+    /// the folder with the natives in it. This is the requested folder, unless it still had other
+    /// natives in use that couldn't be moved out of the way
+    pub directory: PathBuf,
+    /// how many natives jars were extracted
+    pub jars: usize,
+}
+
+/// A natives jar to extract
+#[derive(Debug)]
+struct NativesJar {
+    path: PathBuf,
+    sha1: String,
+    exclude: Vec<String>,
+}
+
+/// Extracts the natives of every library of `version` for the current OS into `natives_directory`
+/// and returns where they ended up.
+///
+/// Only versions before 1.19 have natives to extract, newer versions let LWJGL extract them itself.
+/// Entries that match the `extract.exclude` of a library (e.g `META-INF/`) are skipped.
+///
+/// The natives are extracted into a temporary folder which is then moved into place, so other
+/// instances of the same version that are already running keep working. If the folder already has
+/// the same natives in it, it is reused as is. If it has other natives that can't be moved out of
+/// the way (on windows, while a running game still has them loaded), the natives go into a folder
+/// named after them next to it instead, e.g `natives-0123456789ab`.
+///
+/// Staging folders left behind by crashed launches and old natives that couldn't be removed before
+/// are cleaned up first.
+#[tracing::instrument]
+pub async fn extract_natives(
+    version: &Version,
+    libraries_directory: &Path,
+    natives_directory: &Path,
+) -> Result<ExtractedNatives, NativesError> {
+    let mut jars = vec![];
+
+    for library in version.libraries.iter().flatten() {
+        if let Some(rules) = &library.rules {
+            if !Version::check_library_rules(rules) {
+                continue;
+            }
+        }
+
+        let natives = match library
+            .downloads
+            .as_ref()
+            .and_then(|downloads| library.natives_download(downloads))
+        {
+            Some(natives) => natives,
+            None => continue,
+        };
+
+        let path = libraries_directory.join(
+            natives
+                .path
+                .as_ref()
+                .ok_or_else(|| NativesError::NoPath(library.name.clone()))?,
+        );
+
+        if !path.exists() {
+            return Err(NativesError::MissingFile(path));
+        }
+
+        let exclude = library
+            .extract
+            .as_ref()
+            .map(|extract| extract.exclude.clone())
+            .unwrap_or_default();
+
+        jars.push(NativesJar {
+            path,
+            sha1: natives.sha1.clone(),
+            exclude,
+        });
+    }
+
+    let natives_directory = natives_directory.to_path_buf();
+    let count = jars.len();
+
+    let directory =
+        tokio::task::spawn_blocking(move || extract_into_place(&jars, &natives_directory))
+            .await??;
+
+    Ok(ExtractedNatives {
+        directory,
+        jars: count,
+    })
+}
+
+/// Extracts `jars` and returns the folder they were extracted to
+fn extract_into_place(
+    jars: &[NativesJar],
+    natives_directory: &Path,
+) -> Result<PathBuf, NativesError> {
+    remove_stale_siblings(natives_directory);
+
+    let fingerprint = fingerprint(jars);
+
+    if read_marker(natives_directory).as_deref() == Some(fingerprint.as_str()) {
+        debug!(
+            "{} already has the right natives, reusing it",
+            natives_directory.display()
+        );
+        return Ok(natives_directory.to_path_buf());
+    }
+
+    let staging = sibling(natives_directory, "tmp");
+    trace!("Extracting natives to {}", staging.display());
+    create_dir_all(&staging)?;
+
+    let extracted = jars
+        .iter()
+        .try_for_each(|jar| extract_jar(&jar.path, &staging, &jar.exclude))
+        .and_then(|()| Ok(std::fs::write(staging.join(MARKER_FILE), &fingerprint)?));
+
+    if let Err(err) = extracted {
+        let _ = remove_dir_all(&staging);
+        return Err(err);
+    }
+
+    if let Some(parent) = natives_directory.parent() {
+        create_dir_all(parent)?;
+    }
+
+    // fails if the folder already exists with something in it
+    if rename(&staging, natives_directory).is_err() {
+        if read_marker(natives_directory).as_deref() == Some(fingerprint.as_str()) {
+            debug!("Another launch extracted the same natives first, using those");
+            remove_dir_all(&staging)?;
+            return Ok(natives_directory.to_path_buf());
+        }
+
+        // the old natives might still be in use, so move them out of the way instead of
+        // overwriting them
+        let old = sibling(natives_directory, "old");
+        debug!(
+            "Replacing outdated natives in {}",
+            natives_directory.display()
+        );
+        if let Err(err) = rename(natives_directory, &old) {
+            debug!(
+                "Couldn't move {} out of the way: {}",
+                natives_directory.display(),
+                err
+            );
+            return move_to_own_directory(&staging, natives_directory, &fingerprint);
+        }
+        rename(&staging, natives_directory)?;
+
+        // files that are still loaded by a running game can't be removed on windows
+        if let Err(err) = remove_dir_all(&old) {
+            debug!("Couldn't remove old natives {}: {}", old.display(), err);
+        }
+    }
+
+    debug!(
+        "Extracted {} natives jars to {}",
+        jars.len(),
+        natives_directory.display()
+    );
+    Ok(natives_directory.to_path_buf())
+}
+
+/// Moves freshly extracted natives from `staging` to a folder named after their `fingerprint` next
+/// to `natives_directory`, for when the natives in there can't be replaced
+fn move_to_own_directory(
+    staging: &Path,
+    natives_directory: &Path,
+    fingerprint: &str,
+) -> Result<PathBuf, NativesError> {
+    let mut file_name = natives_directory
+        .file_name()
+        .unwrap_or_default()
+        .to_os_string();
+    file_name.push(format!("-{}", &fingerprint[..12]));
+    let directory = natives_directory.with_file_name(file_name);
+
+    if read_marker(&directory).as_deref() == Some(fingerprint) {
+        debug!("{} already has the right natives", directory.display());
+        remove_dir_all(staging)?;
+        return Ok(directory);
+    }
+
+    // without the marker it was never finished
+    if directory.exists() {
+        remove_dir_all(&directory)?;
+    }
+
+    rename(staging, &directory)?;
+    debug!("Extracted natives to {} instead", directory.display());
+    Ok(directory)
+}
+
+/// Removes the staging folders of crashed launches and old natives that couldn't be removed before.
+/// Staging folders are only removed once they're old, as another launch might still be extracting
+/// into them, and old natives that are still loaded are left for next time
+fn remove_stale_siblings(natives_directory: &Path) {
+    let (parent, name) = match (natives_directory.parent(), natives_directory.file_name()) {
+        (Some(parent), Some(name)) => (parent, name.to_string_lossy()),
+        _ => return,
+    };
+
+    let entries = match read_dir(parent) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    let prefix = format!("{}.", name);
+
+    for entry in entries.flatten() {
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+
+        if !file_name.starts_with(&prefix) {
+            continue;
+        }
+
+        let stale = if file_name.ends_with(".old") {
+            true
+        } else if file_name.ends_with(".tmp") {
+            entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .is_some_and(|age| age > STALE_STAGING_AGE)
+        } else {
+            false
+        };
+
+        if stale {
+            trace!("Removing stale natives folder {}", entry.path().display());
+
+            if let Err(err) = remove_dir_all(entry.path()) {
+                debug!("Couldn't remove {}: {}", entry.path().display(), err);
+            }
+        }
+    }
+}
+
+/// Identifies a set of natives jars, to tell if a natives folder needs to be extracted again
+fn fingerprint(jars: &[NativesJar]) -> String {
+    let mut hasher = Sha1::new();
+
+    for jar in jars {
+        hasher.update(jar.path.to_string_lossy().as_bytes());
+        hasher.update(jar.sha1.as_bytes());
+        hasher.update(jar.exclude.join(",").as_bytes());
+        hasher.update(b"\n");
+    }
+
+    format!("{:x}", hasher.finalize())
+}
+
+fn read_marker(natives_directory: &Path) -> Option<String> {
+    std::fs::read_to_string(natives_directory.join(MARKER_FILE)).ok()
+}
+
+/// A unique path next to `path`, e.g `natives.1234-5678.tmp`
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let unique = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(".{}-{}.{}", std::process::id(), unique, suffix));
+    path.with_file_name(file_name)
+}
+
+fn extract_jar(
+    jar: &Path,
+    natives_directory: &Path,
+    exclude: &[String],
+) -> Result<(), NativesError> {
+    debug!(
+        "Extracting {} to {}",
+        jar.display(),
+        natives_directory.display()
+    );
+    let mut archive = ZipArchive::new(File::open(jar)?)?;
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+
+        if entry.is_dir()
+            || exclude
+                .iter()
+                .any(|prefix| entry.name().starts_with(prefix))
+        {
+            continue;
+        }
+
+        // entries like `../../file` would end up outside of the natives directory
+        let name = match entry.enclosed_name() {
+            Some(name) => PathBuf::from(name),
+            None => continue,
+        };
+
+        let path = natives_directory.join(name);
+        trace!("Extracting {}", path.display());
+
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+
+        io::copy(&mut entry, &mut File::create(&path)?)?;
+    }
+
+    Ok(())
+}
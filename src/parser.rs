@@ -1,11 +1,16 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
 use dunce::canonicalize;
 use tracing::{debug, trace};
 
 use crate::assets::structs::version::{Action, GameRule, JvmRule, Value, Version};
 use crate::assets::structs::version::{GameClass, JvmClass};
+use crate::downloader::Downloader;
 use crate::errors::JavaArgumentsError;
 use crate::launcher::Launcher;
-use crate::util::create_library_download;
+use crate::maven::MavenRepositories;
+use crate::util::{create_library_download, MavenIdentifier};
 
 #[cfg(target_os = "windows")]
 use winsafe::IsWindows10OrGreater;
@@ -58,7 +63,11 @@ impl GameArguments {
             let dynamic_argument = &argument[2..argument.len() - 1].to_string();
             Ok(Self::match_dynamic_argument(launcher_arguments, dynamic_argument)?.to_string())
         } else if argument == "--clientId" {
-            if let Some(_) = &launcher_arguments.authentication_details.client_id {
+            if launcher_arguments
+                .authentication_details
+                .client_id
+                .is_some()
+            {
                 Ok(argument)
             } else {
                 Ok("".to_string()) // dont put in argument if there is no client id
@@ -90,11 +99,11 @@ impl GameArguments {
                 .username
                 .to_owned(),
             "version_name" => launcher_arguments.version_name.to_owned(),
-            "game_directory" => canonicalize(launcher_arguments.game_directory.to_owned())?
+            "game_directory" => canonicalize(&launcher_arguments.game_directory)?
                 .to_str()
                 .ok_or(JavaArgumentsError::NotValidUtf8Path)?
                 .to_owned(),
-            "assets_root" => canonicalize(launcher_arguments.assets_directory.to_owned())?
+            "assets_root" => canonicalize(&launcher_arguments.assets_directory)?
                 .to_str()
                 .ok_or(JavaArgumentsError::NotValidUtf8Path)?
                 .to_owned(),
@@ -105,6 +114,17 @@ impl GameArguments {
                 .access_token
                 .to_owned(),
             "clientid" => client_id,
+            // only used by versions before 1.13
+            "auth_session" => format!(
+                "token:{}:{}",
+                launcher_arguments.authentication_details.access_token,
+                launcher_arguments.authentication_details.uuid
+            ),
+            "user_properties" => "{}".to_string(),
+            "game_assets" => canonicalize(launcher_arguments.game_assets_directory()?)?
+                .to_str()
+                .ok_or(JavaArgumentsError::NotValidUtf8Path)?
+                .to_owned(),
             "auth_xuid" => launcher_arguments
                 .authentication_details
                 .xbox_uid
@@ -150,9 +170,9 @@ impl GameArguments {
         // based of the 1.18 json
         match rule.action {
             Action::Allow => {
-                if let Some(_) = rule.features.is_demo_user {
+                if rule.features.is_demo_user.is_some() {
                     return Ok(launcher_arguments.authentication_details.is_demo_user);
-                } else if let Some(_) = rule.features.has_custom_resolution {
+                } else if rule.features.has_custom_resolution.is_some() {
                     return Ok(launcher_arguments.custom_resolution.is_some());
                 } else {
                     Err(JavaArgumentsError::UnrecognisedAllowRule)
@@ -170,18 +190,17 @@ impl JavaArguments {
         launcher_arguments: &Launcher,
         version_manifest: &Version,
         argument: String,
-        client: reqwest::Client
+        downloader: &Downloader,
     ) -> Result<String, JavaArgumentsError> {
-        let classpath = Self::create_classpath(version_manifest, launcher_arguments, client).await?;
+        let classpath =
+            Self::create_classpath(version_manifest, launcher_arguments, downloader).await?;
 
         Ok(argument
             .replace(
                 "${natives_directory}",
-                //TODO: Add compat with mc version <= 1.16.5 which uses <version>/natives
-                &canonicalize(&launcher_arguments.libraries_directory)?
+                canonicalize(launcher_arguments.natives_directory())?
                     .to_str()
-                    .ok_or(JavaArgumentsError::NotValidUtf8Path)?
-                    .to_string(),
+                    .ok_or(JavaArgumentsError::NotValidUtf8Path)?,
             )
             .replace("${launcher_name}", &launcher_arguments.client_branding)
             .replace("${launcher_version}", &launcher_arguments.launcher_name)
@@ -198,7 +217,7 @@ impl JavaArguments {
         launcher_arguments: &Launcher,
         version_manifest: &Version,
         argument: &JvmClass,
-        client: reqwest::Client
+        downloader: &Downloader,
     ) -> Result<Option<String>, JavaArgumentsError> {
         for rule in &argument.rules {
             if !Self::check_rule(rule)? {
@@ -214,7 +233,7 @@ impl JavaArguments {
                     Value::String(str) => str.to_string(),
                     Value::StringArray(array) => array.join(" "),
                 },
-                client
+                downloader,
             )
             .await?,
         ))
@@ -248,7 +267,7 @@ impl JavaArguments {
                     };
                 }
 
-                if current_allow == false {
+                if !current_allow {
                     return Ok(false);
                 }
 
@@ -268,7 +287,7 @@ impl JavaArguments {
     async fn create_classpath(
         version_manifest: &Version,
         launcher_arguments: &Launcher,
-        client: reqwest::Client
+        downloader: &Downloader,
     ) -> Result<Vec<String>, JavaArgumentsError> {
         let mut cp = vec![];
 
@@ -285,95 +304,61 @@ impl JavaArguments {
 
             let download = if let Some(down) = &library.downloads {
                 down.to_owned()
+            } else if launcher_arguments.offline {
+                // only the path is needed here, and that doesn't need the network
+                let path = MavenIdentifier::from_str(&library.name)?.to_path();
+                cp.push(Self::classpath_entry(
+                    launcher_arguments.libraries_directory.join(path),
+                )?);
+                continue;
             } else {
-                create_library_download(&library.url.as_ref().unwrap(), &library.name, client.clone()).await?
+                create_library_download(
+                    &MavenRepositories::new(library.url.clone()),
+                    &library.name,
+                    downloader,
+                )
+                .await?
             };
 
-            cp.push(
-                canonicalize(
+            // natives-only libraries don't have a jar to put on the classpath
+            if let Some(artifact) = &download.artifact {
+                cp.push(Self::classpath_entry(
                     launcher_arguments.libraries_directory.join(
-                        download
-                            .artifact
+                        artifact
                             .path
                             .as_ref()
                             .ok_or(JavaArgumentsError::NoDownloadArtifactPath)?,
                     ),
-                )?
-                .to_str()
-                .ok_or(JavaArgumentsError::NotValidUtf8Path)?
-                .to_owned(),
-            );
-
-            if let Some(classifiers) = &download.classifiers {
-                match std::env::consts::OS {
-                    "windows" => {
-                        if let Some(windows) = &classifiers.natives_windows {
-                            cp.push(
-                                canonicalize(
-                                    launcher_arguments.libraries_directory.join(
-                                        windows
-                                            .path
-                                            .as_ref()
-                                            .ok_or(JavaArgumentsError::NoLibsPath)?,
-                                    ),
-                                )?
-                                .to_str()
-                                .ok_or(JavaArgumentsError::NotValidUtf8Path)?
-                                .to_owned(),
-                            );
-                        } else {
-                            continue;
-                        }
-                    }
-                    "macos" => {
-                        if let Some(macos) = &classifiers.natives_macos {
-                            cp.push(
-                                canonicalize(launcher_arguments.libraries_directory.join(
-                                    macos.path.as_ref().ok_or(JavaArgumentsError::NoLibsPath)?,
-                                ))?
-                                .to_str()
-                                .ok_or(JavaArgumentsError::NotValidUtf8Path)?
-                                .to_owned(),
-                            );
-                        } else if let Some(osx) = &classifiers.natives_osx {
-                            cp.push(
-                                canonicalize(launcher_arguments.libraries_directory.join(
-                                    osx.path.as_ref().ok_or(JavaArgumentsError::NoLibsPath)?,
-                                ))?
-                                .to_str()
-                                .ok_or(JavaArgumentsError::NotValidUtf8Path)?
-                                .to_owned(),
-                            )
-                        } else {
-                            continue;
-                        }
-                    }
-                    "linux" => {
-                        if let Some(linux) = &classifiers.natives_linux {
-                            cp.push(
-                                canonicalize(launcher_arguments.libraries_directory.join(
-                                    linux.path.as_ref().ok_or(JavaArgumentsError::NoLibsPath)?,
-                                ))?
-                                .to_str()
-                                .ok_or(JavaArgumentsError::NotValidUtf8Path)?
-                                .to_owned(),
-                            );
-                        } else {
-                            continue;
-                        }
-                    }
-                    _ => continue,
-                };
+                )?);
+            }
+
+            if let Some(natives) = library.natives_download(&download) {
+                cp.push(Self::classpath_entry(
+                    launcher_arguments.libraries_directory.join(
+                        natives
+                            .path
+                            .as_ref()
+                            .ok_or(JavaArgumentsError::NoLibsPath)?,
+                    ),
+                )?);
             }
         }
 
-        cp.push(
-            canonicalize(&launcher_arguments.jar_path)?
-                .to_str()
-                .ok_or(JavaArgumentsError::NotValidUtf8Path)?
-                .to_owned(),
-        );
+        cp.push(Self::classpath_entry(launcher_arguments.jar_path.clone())?);
 
         Ok(cp)
     }
+
+    /// Canonicalizes a path for the classpath, failing with [`JavaArgumentsError::MissingFile`] if
+    /// it hasn't been downloaded
+    fn classpath_entry(path: PathBuf) -> Result<String, JavaArgumentsError> {
+        if !path.exists() {
+            return Err(JavaArgumentsError::MissingFile(path));
+        }
+
+        Ok(canonicalize(&path)?
+            .to_str()
+            .ok_or(JavaArgumentsError::NotValidUtf8Path)?
+            .to_owned())
+    }
 }
@@ -0,0 +1,156 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use tracing::{debug, trace};
+
+use crate::assets::structs::launcher_meta::LauncherMeta;
+use crate::assets::structs::version::{ReplacedLibrary, Version};
+use crate::downloader::Downloader;
+use crate::errors::VersionResolveError;
+
+/// Resolves versions that inherit from other versions (e.g a fabric profile inheriting from
+/// vanilla) into a single merged version.
+///
+/// Parents are looked for in `<versions_directory>/<id>/<id>.json` first, and then in the launcher
+/// meta unless the resolver is offline
+#[derive(Debug, Clone)]
+pub struct VersionResolver {
+    /// the versions folder, e.g `.minecraft/versions`
+    pub versions_directory: PathBuf,
+    /// only use versions that are on disk
+    pub offline: bool,
+}
+
+impl VersionResolver {
+    pub fn new(versions_directory: PathBuf) -> Self {
+        Self {
+            versions_directory,
+            offline: false,
+        }
+    }
+
+    /// Only use versions that are on disk
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Finds the version with the given id and merges all of its parents into it
+    #[tracing::instrument]
+    pub async fn resolve(
+        &self,
+        id: &str,
+        downloader: &Downloader,
+    ) -> Result<Version, VersionResolveError> {
+        Ok(self.resolve_with_replaced(id, downloader).await?.0)
+    }
+
+    /// Finds the version with the given id and merges all of its parents into it, and returns the
+    /// libraries of the parents that were replaced by libraries of their children
+    #[tracing::instrument]
+    pub async fn resolve_with_replaced(
+        &self,
+        id: &str,
+        downloader: &Downloader,
+    ) -> Result<(Version, Vec<ReplacedLibrary>), VersionResolveError> {
+        let mut launcher_meta = None;
+        let version = self.load(id, downloader, &mut launcher_meta).await?;
+
+        self.merge_parents(version, downloader, &mut launcher_meta)
+            .await
+    }
+
+    /// Merges all the parents of `version` into it
+    #[tracing::instrument]
+    pub async fn resolve_version(
+        &self,
+        version: Version,
+        downloader: &Downloader,
+    ) -> Result<Version, VersionResolveError> {
+        Ok(self
+            .resolve_version_with_replaced(version, downloader)
+            .await?
+            .0)
+    }
+
+    /// Merges all the parents of `version` into it, and returns the libraries of the parents that
+    /// were replaced by libraries of their children
+    #[tracing::instrument]
+    pub async fn resolve_version_with_replaced(
+        &self,
+        version: Version,
+        downloader: &Downloader,
+    ) -> Result<(Version, Vec<ReplacedLibrary>), VersionResolveError> {
+        self.merge_parents(version, downloader, &mut None).await
+    }
+
+    async fn merge_parents(
+        &self,
+        version: Version,
+        downloader: &Downloader,
+        launcher_meta: &mut Option<LauncherMeta>,
+    ) -> Result<(Version, Vec<ReplacedLibrary>), VersionResolveError> {
+        let mut chain = version.id.clone().into_iter().collect::<Vec<_>>();
+        let mut seen = chain.iter().cloned().collect::<HashSet<_>>();
+        let mut parent_id = version.inherits_from.clone();
+        let mut merged = version;
+        let mut replaced = vec![];
+
+        while let Some(id) = parent_id {
+            chain.push(id.clone());
+            if !seen.insert(id.clone()) {
+                return Err(VersionResolveError::Cycle(chain.join(" -> ")));
+            }
+
+            debug!("Merging parent version {}", id);
+            let parent = self.load(&id, downloader, launcher_meta).await?;
+            parent_id = parent.inherits_from.clone();
+
+            let (version, replaced_by_parent) = merged.merge_with_replaced(parent);
+            merged = version;
+            replaced.extend(replaced_by_parent);
+        }
+
+        trace!("Resolved version chain {}", chain.join(" -> "));
+        // everything it inherits from is merged in now
+        merged.inherits_from = None;
+        Ok((merged, replaced))
+    }
+
+    /// Loads a version from disk, or from the launcher meta if it isn't on disk
+    async fn load(
+        &self,
+        id: &str,
+        downloader: &Downloader,
+        launcher_meta: &mut Option<LauncherMeta>,
+    ) -> Result<Version, VersionResolveError> {
+        let path = self
+            .versions_directory
+            .join(id)
+            .join(format!("{}.json", id));
+
+        if path.exists() {
+            debug!("Loading version {} from {}", id, path.display());
+            let json = tokio::fs::read_to_string(&path).await?;
+            return Ok(serde_json::from_str(&json)?);
+        }
+
+        if self.offline {
+            return Err(VersionResolveError::NotFound(id.to_string()));
+        }
+
+        let launcher_meta = match launcher_meta {
+            Some(launcher_meta) => launcher_meta,
+            None => launcher_meta.insert(LauncherMeta::download_meta(downloader).await?),
+        };
+
+        debug!("Downloading version {}", id);
+        Ok(launcher_meta
+            .versions
+            .iter()
+            .find(|version| version.id == id)
+            .ok_or_else(|| VersionResolveError::NotFound(id.to_string()))?
+            .version(downloader)
+            .await?)
+    }
+}
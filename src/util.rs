@@ -1,157 +1,658 @@
+use std::fmt::Display;
+use std::future::Future;
 use std::ops::{Deref, DerefMut, Div};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
 
-use futures::stream::FuturesUnordered;
 use futures::StreamExt;
-use reqwest::{Client, ClientBuilder};
-use tokio::fs::create_dir_all;
+use reqwest::header::{ACCEPT_RANGES, CONTENT_RANGE, RANGE, RETRY_AFTER};
+use reqwest::{Certificate, Client, ClientBuilder, Method, Proxy, Response, StatusCode};
+use sha1::{Digest, Sha1};
+use tokio::fs::{create_dir_all, OpenOptions};
 use tokio::io::AsyncWriteExt;
 use tokio::sync::watch::Receiver;
-use tokio::task::{self, JoinHandle};
-use tokio_retry::{strategy::FixedInterval, Retry};
-use tracing::{debug, trace};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, trace, warn};
 
 use crate::assets::structs::version::{LibraryDownloads, MappingsClass};
-use crate::errors::{CreateLibraryDownloadError, DownloadError, MavenIdentifierParseError};
+use crate::downloader::{DownloadJob, DownloadSummary, Downloader, RetryPolicy};
+use crate::errors::{
+    ClientError, CreateLibraryDownloadError, DownloadError, MavenIdentifierParseError,
+};
+use crate::maven::MavenRepositories;
+
+/// How [`download_file`] retries and reports a download
+#[derive(Debug, Clone, Copy)]
+pub struct DownloadOptions<'a> {
+    /// how failed attempts are retried
+    pub retry_policy: &'a RetryPolicy,
+    /// how long the server can go without sending anything before the attempt fails
+    pub read_timeout: Option<Duration>,
+    /// what every received chunk is added to, to report progress
+    pub downloaded_bytes: Option<&'a AtomicU64>,
+}
 
+/// Downloads `job.url` to `job.path`, retrying the whole download according to the retry policy
+/// if it fails.
+///
+/// If the job has a SHA-1 and/or size, they are checked while the response is streamed to disk.
+/// A file that doesn't match is deleted and the download is retried.
+///
+/// The file is written to a `.part` file next to `path` and only moved to `path` once it's
+/// complete and verified, so an interrupted download never leaves a broken file behind. If an
+/// attempt fails partway through and the server supports range requests, the next attempt picks
+/// up where the last one stopped.
+///
+/// A `.part` file left behind by a crash is only picked up again when the same file is downloaded
+/// again. [`Downloader::start`] also removes it when the file turns out to be downloaded already.
+///
+/// If the server doesn't send anything for longer than the read timeout, the attempt fails.
+///
+/// Every chunk that is received gets added to `downloaded_bytes`. Bytes that get thrown away are
+/// taken away again, as they'll have to be downloaded again.
 #[tracing::instrument]
-pub fn create_download_task(
-    url: String,
-    path: PathBuf,
-    client: Option<Client>,
-) -> JoinHandle<Result<(), DownloadError>> {
-    trace!("Creating download task for {}", url);
-    tokio::spawn(async move {
-        let client = client.clone().unwrap_or_else(create_client);
-
-        create_dir_all(&path.parent().ok_or(DownloadError::NoPathParent)?).await?;
-
-        // idk how to get rid of clone
-        // hours wasted: 2
-        let action = || {
-            debug!("Attempting to download {}", url);
-            client.get(url.clone()).send()
-        };
+pub async fn download_file(
+    client: &Client,
+    job: &DownloadJob,
+    options: &DownloadOptions<'_>,
+) -> Result<(), DownloadError> {
+    let DownloadJob {
+        url,
+        path,
+        sha1,
+        size,
+    } = job;
+    let (sha1, size) = (sha1.as_deref(), *size);
+
+    create_dir_all(&path.parent().ok_or(DownloadError::NoPathParent)?).await?;
+
+    let temp_path = temp_download_path(path);
+    // left over from a previous run that got interrupted
+    remove_if_exists(&temp_path).await?;
+
+    let mut partial = PartialDownload {
+        path: &temp_path,
+        counted: 0,
+        accepts_ranges: false,
+        downloaded_bytes: options.downloaded_bytes,
+    };
+
+    let mut failed_attempts = 0;
+
+    loop {
+        let result =
+            try_download_file(client, url, &mut partial, sha1, size, options.read_timeout).await;
+        partial.recount().await;
+
+        match result {
+            Ok(()) => break,
+            Err(err) => match options.retry_policy.retry_delay(failed_attempts + 1, &err) {
+                Some(delay) => {
+                    failed_attempts += 1;
+                    debug!(
+                        "Download of {} failed ({}), retrying in {:?}",
+                        url, err, delay
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                None => {
+                    remove_if_exists(&temp_path).await?;
+                    partial.recount().await;
+                    return Err(err);
+                }
+            },
+        }
+    }
 
-        let retry_strategy = FixedInterval::from_millis(100).take(3);
+    trace!("Moving {} to {}", temp_path.display(), path.display());
+    tokio::fs::rename(&temp_path, path).await?;
+
+    debug!("Downloaded {}", url);
+    Ok(())
+}
+
+/// The path a download to `path` is written to before it is complete
+pub(crate) fn temp_download_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".part");
+    path.with_file_name(file_name)
+}
 
-        let response = Retry::spawn(retry_strategy, action).await?;
+pub(crate) async fn remove_if_exists(path: &Path) -> Result<(), std::io::Error> {
+    match tokio::fs::remove_file(path).await {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
 
-        trace!("Creating file at {}", &path.display());
-        let mut file = tokio::fs::File::create(&path).await?;
+/// A `.part` file that is being downloaded to, kept across attempts so they can resume it
+#[derive(Debug)]
+struct PartialDownload<'a> {
+    path: &'a Path,
+    /// how many bytes of this file have been added to `downloaded_bytes`
+    counted: u64,
+    /// whether the server said it supports range requests for this file
+    accepts_ranges: bool,
+    downloaded_bytes: Option<&'a AtomicU64>,
+}
 
-        trace!("Writing response to file");
-        let mut stream = response.bytes_stream();
+impl PartialDownload<'_> {
+    async fn len(&self) -> u64 {
+        tokio::fs::metadata(self.path)
+            .await
+            .map(|metadata| metadata.len())
+            .unwrap_or(0)
+    }
 
-        while let Some(item) = stream.next().await {
-            let chunk = item?;
-            file.write_all(&chunk).await?;
+    fn count(&mut self, bytes: u64) {
+        self.counted += bytes;
+        if let Some(downloaded_bytes) = self.downloaded_bytes {
+            downloaded_bytes.fetch_add(bytes, Ordering::Relaxed);
         }
-        trace!("Wrote response to file");
+    }
 
-        debug!("Downloaded {}", url);
-        Ok(())
-    })
+    /// Makes sure only the bytes that are actually in the file count towards the progress
+    async fn recount(&mut self) {
+        let len = self.len().await;
+        if let Some(downloaded_bytes) = self.downloaded_bytes {
+            downloaded_bytes.fetch_add(len, Ordering::Relaxed);
+            downloaded_bytes.fetch_sub(self.counted, Ordering::Relaxed);
+        }
+        self.counted = len;
+    }
 }
 
-pub type ListOfResultHandles = FuturesUnordered<task::JoinHandle<Result<(), DownloadError>>>;
+async fn try_download_file(
+    client: &Client,
+    url: &str,
+    partial: &mut PartialDownload<'_>,
+    sha1: Option<&str>,
+    size: Option<u64>,
+    read_timeout: Option<Duration>,
+) -> Result<(), DownloadError> {
+    let mut offset = if partial.accepts_ranges {
+        partial.len().await
+    } else {
+        0
+    };
+
+    // the file is already too big to be right, so it's not worth resuming
+    if size.is_some_and(|size| offset >= size) {
+        offset = 0;
+    }
+
+    let mut request = client.get(url);
+    if offset > 0 {
+        debug!("Resuming download of {} from byte {}", url, offset);
+        request = request.header(RANGE, format!("bytes={}-", offset));
+    } else {
+        debug!("Attempting to download {}", url);
+    }
+
+    let response = with_read_timeout(read_timeout, request.send()).await??;
+
+    if offset > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        // the part file doesn't line up with what's on the server anymore, start over
+        partial.accepts_ranges = false;
+        tokio::fs::File::create(partial.path).await?;
+        return Err(DownloadError::UnexpectedContentRange);
+    }
 
-// net.fabricmc:tiny-mappings-parser:0.3.0+build.17
+    if !response.status().is_success() {
+        return Err(DownloadError::HttpStatus {
+            status: response.status(),
+            retry_after: retry_after(&response),
+        });
+    }
+
+    partial.accepts_ranges = response.status() == StatusCode::PARTIAL_CONTENT
+        || response
+            .headers()
+            .get(ACCEPT_RANGES)
+            .is_some_and(|value| value == "bytes");
+
+    let mut hasher = Sha1::new();
+    let mut written = 0;
+
+    let mut file = if offset > 0 && response.status() == StatusCode::PARTIAL_CONTENT {
+        if content_range_start(&response) != Some(offset) {
+            // got a different part of the file than what was asked for, start over without ranges
+            partial.accepts_ranges = false;
+            tokio::fs::File::create(partial.path).await?;
+            return Err(DownloadError::UnexpectedContentRange);
+        }
+
+        trace!("Appending to {}", partial.path.display());
+        // the hash has to include what was downloaded before
+        hasher.update(&tokio::fs::read(partial.path).await?);
+        written = offset;
+        OpenOptions::new().append(true).open(partial.path).await?
+    } else {
+        if offset > 0 {
+            debug!("{} ignored the range request, downloading everything", url);
+        }
+
+        trace!("Creating file at {}", partial.path.display());
+        let file = tokio::fs::File::create(partial.path).await?;
+        partial.recount().await;
+        file
+    };
+
+    trace!("Writing response to file");
+    let mut stream = response.bytes_stream();
+
+    while let Some(item) = with_read_timeout(read_timeout, stream.next()).await? {
+        let chunk = item?;
+        written += chunk.len() as u64;
+
+        // no point in downloading the rest if we already know it's wrong
+        if let Some(expected) = size {
+            if written > expected {
+                drop(file);
+                return discard_download(
+                    partial.path,
+                    DownloadError::SizeMismatch {
+                        expected,
+                        actual: written,
+                    },
+                )
+                .await;
+            }
+        }
+
+        hasher.update(&chunk);
+        file.write_all(&chunk).await?;
+        partial.count(chunk.len() as u64);
+    }
+    file.flush().await?;
+    drop(file);
+    trace!("Wrote response to file");
+
+    if let Some(expected) = size {
+        if written != expected {
+            return discard_download(
+                partial.path,
+                DownloadError::SizeMismatch {
+                    expected,
+                    actual: written,
+                },
+            )
+            .await;
+        }
+    }
+
+    if let Some(expected) = sha1 {
+        let actual = format!("{:x}", hasher.finalize());
+        if !actual.eq_ignore_ascii_case(expected) {
+            return discard_download(
+                partial.path,
+                DownloadError::Sha1Mismatch {
+                    expected: expected.to_string(),
+                    actual,
+                },
+            )
+            .await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Fails with [`DownloadError::ReadTimeout`] if `future` takes longer than `read_timeout`
+async fn with_read_timeout<T>(
+    read_timeout: Option<Duration>,
+    future: impl Future<Output = T>,
+) -> Result<T, DownloadError> {
+    match read_timeout {
+        Some(read_timeout) => tokio::time::timeout(read_timeout, future)
+            .await
+            .map_err(|_elapsed| DownloadError::ReadTimeout),
+        None => Ok(future.await),
+    }
+}
+
+/// Gets how long the server asked us to wait before trying again from the `Retry-After` header,
+/// which can either be an amount of seconds or a date
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+
+    match value.parse() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => httpdate::parse_http_date(value)
+            .ok()?
+            .duration_since(SystemTime::now())
+            .ok(),
+    }
+}
+
+/// Gets where the body of a `206 Partial Content` response starts from its `Content-Range` header
+fn content_range_start(response: &Response) -> Option<u64> {
+    // Content-Range: bytes <start>-<end>/<size>
+    response
+        .headers()
+        .get(CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .strip_prefix("bytes ")?
+        .split('-')
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Removes a download that failed verification and returns the verification error
+async fn discard_download(path: &Path, error: DownloadError) -> Result<(), DownloadError> {
+    warn!(
+        "Download to {} is invalid ({}), removing it",
+        path.display(),
+        error
+    );
+    tokio::fs::remove_file(path).await?;
+    Err(error)
+}
+
+/// Checks if the file at `path` exists and has the given size and SHA-1
+///
+/// The size is checked first so missing or truncated files don't get hashed
+#[tracing::instrument]
+pub async fn is_already_downloaded(path: &Path, sha1: &str, size: u64) -> bool {
+    match tokio::fs::metadata(path).await {
+        Ok(metadata) if metadata.is_file() && metadata.len() == size => {}
+        _ => return false,
+    }
+
+    let contents = match tokio::fs::read(path).await {
+        Ok(contents) => contents,
+        Err(_) => return false,
+    };
+
+    let valid = format!("{:x}", Sha1::digest(&contents)).eq_ignore_ascii_case(sha1);
+    trace!("{} is already downloaded: {}", path.display(), valid);
+    valid
+}
+
+/// A maven coordinate in the `group:artifact:version[:classifier][@extension]` format, e.g
+/// `net.fabricmc:tiny-mappings-parser:0.3.0+build.17`, `org.lwjgl:lwjgl:3.3.1:natives-linux` or
+/// `de.oceanlabs.mcp:mcp_config:1.19-20220607.102129@zip`
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MavenIdentifier {
     pub group_id: String,
     pub artifact_id: String,
     pub version: String,
+    /// e.g `natives-linux` or `sources`
+    pub classifier: Option<String>,
+    /// the file extension, `jar` if not specified
+    pub extension: String,
+}
+
+impl MavenIdentifier {
+    /// The extension used when a coordinate doesn't specify one
+    pub const DEFAULT_EXTENSION: &'static str = "jar";
+
+    /// The name of the file, e.g `lwjgl-3.3.1-natives-linux.jar`
+    pub fn file_name(&self) -> String {
+        match &self.classifier {
+            Some(classifier) => format!(
+                "{}-{}-{}.{}",
+                self.artifact_id, self.version, classifier, self.extension
+            ),
+            None => format!("{}-{}.{}", self.artifact_id, self.version, self.extension),
+        }
+    }
+
+    /// The path of the file relative to the root of a maven repository or the libraries folder
+    pub fn to_path(&self) -> String {
+        format!(
+            "{}/{}/{}/{}",
+            self.group_id.replace('.', "/"),
+            self.artifact_id,
+            self.version,
+            self.file_name()
+        )
+    }
+
+    /// The URL of the file in the maven repository at `base`
+    pub fn to_url(&self, base: &str) -> String {
+        format!("{}/{}", base.trim_end_matches('/'), self.to_path())
+    }
+}
+
+impl Display for MavenIdentifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.group_id, self.artifact_id, self.version)?;
+
+        if let Some(classifier) = &self.classifier {
+            write!(f, ":{}", classifier)?;
+        }
+
+        if self.extension != Self::DEFAULT_EXTENSION {
+            write!(f, "@{}", self.extension)?;
+        }
+
+        Ok(())
+    }
 }
 
 impl FromStr for MavenIdentifier {
     type Err = MavenIdentifierParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut parts = s.split(":");
-        let group_id = parts
-            .next()
-            .ok_or(MavenIdentifierParseError::NotEnoughArgs)?
-            .to_string();
-        let artifact_id = parts
-            .next()
-            .ok_or(MavenIdentifierParseError::NotEnoughArgs)?
-            .to_string();
-        let version = parts
-            .next()
-            .ok_or(MavenIdentifierParseError::NotEnoughArgs)?
-            .to_string();
-
-        Ok(Self {
-            group_id,
-            artifact_id,
-            version,
-        })
+        let (coordinate, extension) = match s.rsplit_once('@') {
+            Some((coordinate, extension)) => (coordinate, extension),
+            None => (s, Self::DEFAULT_EXTENSION),
+        };
+
+        let parts = coordinate.split(':').collect::<Vec<_>>();
+
+        if parts.len() > 4 {
+            return Err(MavenIdentifierParseError::TooManyArgs);
+        }
+
+        if extension.is_empty() || parts.iter().any(|part| part.is_empty()) {
+            return Err(MavenIdentifierParseError::EmptyPart);
+        }
+
+        match parts[..] {
+            [group_id, artifact_id, version, ref classifier @ ..] => Ok(Self {
+                group_id: group_id.to_string(),
+                artifact_id: artifact_id.to_string(),
+                version: version.to_string(),
+                classifier: classifier.first().map(|classifier| classifier.to_string()),
+                extension: extension.to_string(),
+            }),
+            _ => Err(MavenIdentifierParseError::NotEnoughArgs),
+        }
     }
 }
 
+/// Creates the downloads of a library that only has a maven name, from the first repository in
+/// `repositories` that has it
 pub async fn create_library_download(
-    url: &str,
+    repositories: &MavenRepositories,
     name: &str,
-    client: Client,
+    downloader: &Downloader,
 ) -> Result<LibraryDownloads, CreateLibraryDownloadError> {
-    let identifier = MavenIdentifier::from_str(name)?;
-
-    let maven_url = format!(
-        "{}/{}/{}/{}-{}.jar",
-        identifier.group_id.replace(".", "/"),
-        identifier.artifact_id,
-        identifier.version,
-        identifier.artifact_id,
-        identifier.version,
-    );
+    let artifact = repositories
+        .resolve(&MavenIdentifier::from_str(name)?, downloader)
+        .await?;
 
-    let download_url = format!("{}{}", &url, &maven_url);
+    let download_url = artifact.url;
 
-    let size = client
-        .head(download_url.clone())
-        .send()
-        .await?
-        .content_length()
+    let size = artifact
+        .size
         .ok_or(CreateLibraryDownloadError::NoContentLength)?;
 
-    let sha1 = client
-        .get(format!("{}.sha1", &download_url))
-        .send()
+    let sha1 = downloader
+        .request(Method::GET, &format!("{}.sha1", &download_url))
         .await?
         .text()
         .await?;
 
     Ok(LibraryDownloads {
-        artifact: MappingsClass {
-            sha1,
+        artifact: Some(MappingsClass {
+            // some repositories put the file name after the hash
+            sha1: sha1
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_string(),
             size,
             url: download_url,
-            path: Some(maven_url),
-        },
+            path: Some(artifact.identifier.to_path()),
+        }),
         classifiers: None,
     })
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Default)]
 pub struct DownloadProgress {
     pub total_size: u64,
     pub finished: u64,
+    /// the total size of all files in bytes, as given by the manifest
+    ///
+    /// files without a known size don't count towards this
+    pub total_bytes: u64,
+    /// how many bytes have been downloaded so far, including files that were already downloaded
+    pub downloaded_bytes: u64,
+    /// how many bytes were downloaded per second recently
+    pub bytes_per_second: u64,
+}
+
+impl DownloadProgress {
+    /// An estimate of how long it will take to download the remaining bytes at the current speed
+    pub fn eta(&self) -> Option<Duration> {
+        if self.bytes_per_second == 0 {
+            return None;
+        }
+
+        let remaining = self.total_bytes.saturating_sub(self.downloaded_bytes);
+        Some(Duration::from_secs_f64(
+            remaining as f64 / self.bytes_per_second as f64,
+        ))
+    }
 }
 
 pub struct DownloadWatcher {
     pub progress_watcher: Receiver<DownloadProgress>,
-    pub download_task: JoinHandle<()>,
+    /// resolves to what happened to each file once all downloads are done or have been cancelled
+    pub download_task: JoinHandle<DownloadSummary>,
+    /// cancels every download that hasn't finished yet when cancelled
+    pub cancellation_token: CancellationToken,
+}
+
+impl DownloadWatcher {
+    /// Stops all downloads that haven't finished yet and removes their partially downloaded files.
+    ///
+    /// `download_task` still resolves, with a [`DownloadStatus::Cancelled`] summary
+    ///
+    /// [`DownloadStatus::Cancelled`]: crate::downloader::DownloadStatus::Cancelled
+    pub fn cancel(&self) {
+        self.cancellation_token.cancel();
+    }
+}
+
+/// The user agent sent with every request if no other one is configured
+pub const DEFAULT_USER_AGENT: &str =
+    concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// How the HTTP client used for every request is set up
+#[derive(Debug, Clone, Default)]
+pub struct ClientConfig {
+    /// the proxy to send every request through, if any
+    pub proxy: Option<ProxyConfig>,
+    /// extra root certificates to trust, e.g for a TLS-intercepting gateway
+    ///
+    /// each one is the contents of a PEM file, which can contain multiple certificates
+    pub root_certificates: Vec<Vec<u8>>,
+    /// how long to wait for a connection to be made
+    pub connect_timeout: Option<Duration>,
+    /// how long a download can go without receiving anything before it fails
+    ///
+    /// this isn't a setting of the client itself, so it only applies to downloaders created with
+    /// [`Downloader::from_config`]
+    pub read_timeout: Option<Duration>,
+    /// the user agent to send, [`DEFAULT_USER_AGENT`] if not set
+    pub user_agent: Option<String>,
+}
+
+/// A HTTP, HTTPS or SOCKS5 proxy
+#[derive(Debug, Clone)]
+pub struct ProxyConfig {
+    /// the URL of the proxy, e.g `http://proxy.example.com:8080` or `socks5://127.0.0.1:1080`
+    pub url: String,
+    /// the username and password to authenticate with, if the proxy needs them
+    pub credentials: Option<ProxyCredentials>,
 }
 
-pub fn create_client() -> Client {
-    ClientBuilder::new()
+/// The username and password for a proxy
+#[derive(Clone)]
+pub struct ProxyCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+// the password would otherwise end up in the logs
+impl std::fmt::Debug for ProxyCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProxyCredentials")
+            .field("username", &self.username)
+            .field("password", &"<hidden>")
+            .finish()
+    }
+}
+
+/// Creates the HTTP client used for every request
+#[tracing::instrument]
+pub fn create_client(config: &ClientConfig) -> Result<Client, ClientError> {
+    let mut builder = ClientBuilder::new()
         .connection_verbose(true)
         .pool_idle_timeout(Some(Duration::from_secs(600)))
         .tcp_keepalive(Some(Duration::from_secs(30)))
-        .build()
-        .unwrap()
+        .user_agent(config.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT));
+
+    if let Some(proxy_config) = &config.proxy {
+        debug!("Using proxy {}", proxy_config.url);
+        let mut proxy = Proxy::all(&proxy_config.url).map_err(ClientError::InvalidProxy)?;
+        if let Some(credentials) = &proxy_config.credentials {
+            proxy = proxy.basic_auth(&credentials.username, &credentials.password);
+        }
+        builder = builder.proxy(proxy);
+    }
+
+    for pem in &config.root_certificates {
+        for certificate in parse_certificates(pem)? {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    if let Some(connect_timeout) = config.connect_timeout {
+        builder = builder.connect_timeout(connect_timeout);
+    }
+
+    builder.build().map_err(ClientError::BuildError)
+}
+
+/// Parses every certificate in a PEM file
+fn parse_certificates(pem: &[u8]) -> Result<Vec<Certificate>, ClientError> {
+    const END: &str = "-----END CERTIFICATE-----";
+
+    let certificates = String::from_utf8_lossy(pem)
+        .split_inclusive(END)
+        .filter(|certificate| certificate.contains(END))
+        .map(|certificate| {
+            Certificate::from_pem(certificate.as_bytes()).map_err(ClientError::InvalidCertificate)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if certificates.is_empty() {
+        return Err(ClientError::NoCertificates);
+    }
+
+    trace!("Parsed {} root certificates", certificates.len());
+    Ok(certificates)
 }
 
 pub struct DivPathBuf(pub PathBuf);
@@ -0,0 +1,99 @@
+//! A tiny HTTP server for tests, which answers every request with a canned response
+
+// every test binary uses a different part of this
+#![allow(dead_code)]
+
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// A server listening on a random local port
+pub struct TestServer {
+    /// the base url of the server, e.g `http://127.0.0.1:1234`
+    pub url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl TestServer {
+    /// Starts a server that answers each request with what `respond` returns for the request line
+    /// and headers
+    pub async fn start(respond: impl Fn(&str) -> Vec<u8> + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let respond = Arc::new(respond);
+
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let request = read_request(&mut stream).await;
+                let response = respond(&request);
+                recorded.lock().unwrap().push(request);
+
+                // a response can be cut short on purpose, so a reset is fine
+                let _ = stream.write_all(&response).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+
+        Self { url, requests }
+    }
+
+    /// Every request received so far, lowercased
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Reads the request line and headers. The body is ignored, as the client only sends GETs
+async fn read_request(stream: &mut tokio::net::TcpStream) -> String {
+    let mut request = vec![];
+    let mut buf = [0; 1024];
+
+    while !request.ends_with(b"\r\n\r\n") {
+        let read = stream.read(&mut buf).await.unwrap();
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..read]);
+    }
+
+    String::from_utf8_lossy(&request).to_lowercase()
+}
+
+/// A response with `body` as its whole content
+pub fn response(status: &str, headers: &[(&str, &str)], body: &[u8]) -> Vec<u8> {
+    truncated_response(status, headers, body, body.len())
+}
+
+/// A response that claims to be `content_length` bytes long, but only sends `body`
+pub fn truncated_response(
+    status: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+    content_length: usize,
+) -> Vec<u8> {
+    let mut response = format!("HTTP/1.1 {status}\r\nContent-Length: {content_length}\r\n");
+
+    for (name, value) in headers {
+        response.push_str(&format!("{name}: {value}\r\n"));
+    }
+
+    response.push_str("Connection: close\r\n\r\n");
+
+    let mut response = response.into_bytes();
+    response.extend_from_slice(body);
+    response
+}
+
+/// The SHA-1 of `data` as lowercase hex
+pub fn sha1_hex(data: &[u8]) -> String {
+    use sha1::{Digest, Sha1};
+
+    Sha1::digest(data)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
@@ -0,0 +1,177 @@
+//! Resuming interrupted downloads and cleaning up after them
+
+mod common;
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use common::{response, sha1_hex, truncated_response, TestServer};
+use copper::downloader::{Backoff, DownloadJob, Downloader, RetryPolicy};
+use copper::errors::DownloadError;
+use copper::util::{download_file, DownloadOptions};
+
+const FILE: &[u8] = b"0123456789";
+
+/// The outcome of downloading [`FILE`] from a server that drops the first attempt halfway through
+struct Resumed {
+    result: Result<(), DownloadError>,
+    requests: Vec<String>,
+    contents: Option<Vec<u8>>,
+    downloaded_bytes: u64,
+}
+
+/// Downloads [`FILE`] from a server that advertises range support, sends the first 5 bytes and
+/// closes the connection. Later requests are answered with `retries` in order
+async fn download_resumed(max_attempts: u32, retries: Vec<Vec<u8>>) -> Resumed {
+    let attempt = AtomicUsize::new(0);
+    let server = TestServer::start(move |_| match attempt.fetch_add(1, Ordering::SeqCst) {
+        0 => truncated_response(
+            "200 OK",
+            &[("Accept-Ranges", "bytes")],
+            &FILE[..5],
+            FILE.len(),
+        ),
+        n => retries[n - 1].clone(),
+    })
+    .await;
+
+    let directory = tempfile::tempdir().unwrap();
+    let path = directory.path().join("file.bin");
+    let retry_policy = RetryPolicy {
+        max_attempts,
+        backoff: Backoff::Fixed(Duration::ZERO),
+        jitter: false,
+        ..RetryPolicy::default()
+    };
+    let downloaded_bytes = AtomicU64::new(0);
+
+    let result = download_file(
+        &reqwest::Client::new(),
+        &DownloadJob {
+            url: format!("{}/file.bin", server.url),
+            path: path.clone(),
+            sha1: Some(sha1_hex(FILE)),
+            size: Some(FILE.len() as u64),
+        },
+        &DownloadOptions {
+            retry_policy: &retry_policy,
+            read_timeout: None,
+            downloaded_bytes: Some(&downloaded_bytes),
+        },
+    )
+    .await;
+
+    Resumed {
+        result,
+        requests: server.requests(),
+        contents: std::fs::read(&path).ok(),
+        downloaded_bytes: downloaded_bytes.load(Ordering::SeqCst),
+    }
+}
+
+#[tokio::test]
+async fn resumes_from_partial_content() {
+    let resumed = download_resumed(
+        2,
+        vec![response(
+            "206 Partial Content",
+            &[("Content-Range", "bytes 5-9/10")],
+            &FILE[5..],
+        )],
+    )
+    .await;
+
+    resumed.result.unwrap();
+    assert!(resumed.requests[1].contains("range: bytes=5-"));
+    assert_eq!(resumed.contents.as_deref(), Some(FILE));
+    assert_eq!(resumed.downloaded_bytes, FILE.len() as u64);
+}
+
+#[tokio::test]
+async fn restarts_when_the_range_is_ignored() {
+    let resumed = download_resumed(2, vec![response("200 OK", &[], FILE)]).await;
+
+    resumed.result.unwrap();
+    assert!(resumed.requests[1].contains("range: bytes=5-"));
+    assert_eq!(resumed.contents.as_deref(), Some(FILE));
+    assert_eq!(resumed.downloaded_bytes, FILE.len() as u64);
+}
+
+#[tokio::test]
+async fn restarts_without_a_range_after_416() {
+    let resumed = download_resumed(
+        3,
+        vec![
+            response("416 Range Not Satisfiable", &[], b""),
+            response("200 OK", &[], FILE),
+        ],
+    )
+    .await;
+
+    resumed.result.unwrap();
+    assert!(resumed.requests[1].contains("range: bytes=5-"));
+    assert!(!resumed.requests[2].contains("range:"));
+    assert_eq!(resumed.contents.as_deref(), Some(FILE));
+    assert_eq!(resumed.downloaded_bytes, FILE.len() as u64);
+}
+
+#[tokio::test]
+async fn restarts_without_a_range_after_a_mismatched_content_range() {
+    let resumed = download_resumed(
+        3,
+        vec![
+            response(
+                "206 Partial Content",
+                &[("Content-Range", "bytes 3-9/10")],
+                &FILE[3..],
+            ),
+            response("200 OK", &[], FILE),
+        ],
+    )
+    .await;
+
+    resumed.result.unwrap();
+    assert!(resumed.requests[1].contains("range: bytes=5-"));
+    assert!(!resumed.requests[2].contains("range:"));
+    assert_eq!(resumed.contents.as_deref(), Some(FILE));
+    assert_eq!(resumed.downloaded_bytes, FILE.len() as u64);
+}
+
+#[tokio::test]
+async fn uncounts_the_bytes_of_a_failed_download() {
+    let resumed = download_resumed(1, vec![]).await;
+
+    assert!(matches!(
+        resumed.result,
+        Err(DownloadError::RequestError(_))
+    ));
+    assert_eq!(resumed.contents, None);
+    assert_eq!(resumed.downloaded_bytes, 0);
+}
+
+#[tokio::test]
+async fn skips_files_that_are_already_downloaded() {
+    let directory = tempfile::tempdir().unwrap();
+    let path = directory.path().join("file.bin");
+    let part = directory.path().join("file.bin.part");
+    std::fs::write(&path, FILE).unwrap();
+    std::fs::write(&part, &FILE[..5]).unwrap();
+
+    let watcher = Downloader::with_defaults()
+        .unwrap()
+        .start(vec![DownloadJob {
+            // never requested, as the file is already there
+            url: "http://127.0.0.1:9/file.bin".to_string(),
+            path: path.clone(),
+            sha1: Some(sha1_hex(FILE)),
+            size: Some(FILE.len() as u64),
+        }]);
+    let summary = watcher.download_task.await.unwrap();
+    let progress = *watcher.progress_watcher.borrow();
+
+    assert_eq!(summary.skipped.len(), 1);
+    assert!(!part.exists());
+    assert_eq!(progress.finished, 1);
+    assert_eq!(progress.downloaded_bytes, FILE.len() as u64);
+    assert_eq!(progress.bytes_per_second, 0);
+}
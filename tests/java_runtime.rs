@@ -0,0 +1,209 @@
+//! Installing a java runtime from a local runtime index
+
+mod common;
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use common::{response, sha1_hex, TestServer};
+use copper::downloader::Downloader;
+use copper::errors::JavaRuntimeError;
+use copper::java::runtime::JavaRuntimeManager;
+use serde_json::{json, Value};
+
+const COMPONENT: &str = "java-runtime-test";
+const JAVA: &[u8] = b"#!/bin/sh\necho java\n";
+const MODULES: &[u8] = b"the modules of the runtime, compressed with lzma";
+
+/// A server that serves `all.json` for a single runtime, its manifest and every file in
+/// `downloads`. `files` gets the url of the server and returns the files of the manifest.
+///
+/// Returns the server and the SHA-1 of the manifest
+async fn serve_runtime(
+    files: impl FnOnce(&str) -> Value,
+    downloads: Vec<(&str, Vec<u8>)>,
+) -> (TestServer, String) {
+    let routes = Arc::new(Mutex::new(HashMap::<String, Vec<u8>>::new()));
+    let served = routes.clone();
+    let server = TestServer::start(move |request| {
+        let path = request.split_whitespace().nth(1).unwrap_or_default();
+
+        match served.lock().unwrap().get(path) {
+            Some(body) => response("200 OK", &[], body),
+            None => response("404 Not Found", &[], b""),
+        }
+    })
+    .await;
+
+    let manifest = serde_json::to_vec(&json!({ "files": files(&server.url) })).unwrap();
+    let manifest_sha1 = sha1_hex(&manifest);
+    let index = serde_json::to_vec(&json!({
+        "linux": {
+            COMPONENT: [{
+                "availability": { "group": 1, "progress": 100 },
+                "manifest": {
+                    "sha1": manifest_sha1,
+                    "size": manifest.len(),
+                    "url": format!("{}/manifest.json", server.url),
+                },
+                "version": { "name": "17.0.3", "released": "2022-05-18T16:25:47+00:00" },
+            }],
+        },
+    }))
+    .unwrap();
+
+    let mut routes = routes.lock().unwrap();
+    routes.insert("/all.json".to_string(), index);
+    routes.insert("/manifest.json".to_string(), manifest);
+    for (path, body) in downloads {
+        routes.insert(path.to_string(), body);
+    }
+    drop(routes);
+
+    (server, manifest_sha1)
+}
+
+/// The manifest entry of a file that is served at `path`
+fn download(server_url: &str, path: &str, data: &[u8]) -> Value {
+    json!({
+        "sha1": sha1_hex(data),
+        "size": data.len(),
+        "url": format!("{server_url}{path}"),
+    })
+}
+
+fn manager(runtime_directory: &Path, server: &TestServer) -> JavaRuntimeManager {
+    JavaRuntimeManager::new(runtime_directory.to_path_buf())
+        .with_index_url(format!("{}/all.json", server.url))
+        .with_platform("linux")
+}
+
+#[tokio::test]
+async fn installs_a_runtime() {
+    let mut compressed = vec![];
+    lzma_rs::lzma_compress(&mut &MODULES[..], &mut compressed).unwrap();
+
+    let (server, manifest_sha1) = serve_runtime(
+        |url| {
+            json!({
+                "bin": { "type": "directory" },
+                "bin/java": {
+                    "type": "file",
+                    "executable": true,
+                    "downloads": { "raw": download(url, "/java", JAVA) },
+                },
+                "bin/java-link": { "type": "link", "target": "java" },
+                "conf": { "type": "directory" },
+                "lib/modules": {
+                    "type": "file",
+                    "executable": false,
+                    "downloads": {
+                        // the raw file isn't served, so this only works if the lzma one is used
+                        "raw": download(url, "/modules", MODULES),
+                        "lzma": download(url, "/modules.lzma", &compressed),
+                    },
+                },
+            })
+        },
+        vec![
+            ("/java", JAVA.to_vec()),
+            ("/modules.lzma", compressed.clone()),
+        ],
+    )
+    .await;
+
+    let runtime_directory = tempfile::tempdir().unwrap();
+    let manager = manager(runtime_directory.path(), &server);
+    let downloader = Downloader::with_defaults().unwrap();
+
+    let java_path = manager.install(COMPONENT, &downloader).await.unwrap();
+    let directory = manager.component_directory(COMPONENT);
+
+    assert_eq!(java_path, directory.join("bin/java"));
+    assert_eq!(std::fs::read(&java_path).unwrap(), JAVA);
+    assert_eq!(
+        std::fs::read(directory.join("lib/modules")).unwrap(),
+        MODULES
+    );
+    assert!(!directory.join("lib/modules.lzma").exists());
+    assert!(directory.join("conf").is_dir());
+    assert_eq!(
+        std::fs::read_to_string(directory.join(".copper-runtime")).unwrap(),
+        manifest_sha1
+    );
+    assert_eq!(manager.installed_java_path(COMPONENT), Some(java_path));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = std::fs::metadata(directory.join("bin/java"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o111, 0o111);
+        assert_eq!(
+            std::fs::read_link(directory.join("bin/java-link")).unwrap(),
+            Path::new("java")
+        );
+    }
+}
+
+#[tokio::test]
+async fn refuses_paths_outside_the_runtime() {
+    let link = |target: &str| {
+        json!({
+            "bin": { "type": "directory" },
+            "bin/escape": { "type": "link", "target": target },
+        })
+    };
+
+    let manifests = [
+        json!({ "../escaped": { "type": "directory" } }),
+        link("../../../../etc"),
+        link("../../.."),
+        link("bin/../../.."),
+        link("/etc"),
+    ];
+
+    for files in manifests {
+        let (server, _) = serve_runtime(|_| files.clone(), vec![]).await;
+
+        let runtime_directory = tempfile::tempdir().unwrap();
+        let manager = manager(runtime_directory.path(), &server);
+        let downloader = Downloader::with_defaults().unwrap();
+
+        let result = manager.install(COMPONENT, &downloader).await;
+
+        assert!(
+            matches!(result, Err(JavaRuntimeError::InvalidPath(_))),
+            "{files} was installed"
+        );
+
+        let directory = manager.component_directory(COMPONENT);
+        assert!(!directory.join("../escaped").exists());
+        assert!(std::fs::symlink_metadata(directory.join("bin/escape")).is_err());
+    }
+}
+
+#[tokio::test]
+async fn allows_links_inside_the_runtime() {
+    let (server, _) = serve_runtime(
+        |_| {
+            json!({
+                "bin": { "type": "directory" },
+                "lib": { "type": "directory" },
+                "bin/lib": { "type": "link", "target": "../lib" },
+            })
+        },
+        vec![],
+    )
+    .await;
+
+    let runtime_directory = tempfile::tempdir().unwrap();
+    let manager = manager(runtime_directory.path(), &server);
+    let downloader = Downloader::with_defaults().unwrap();
+
+    manager.install(COMPONENT, &downloader).await.unwrap();
+}
@@ -0,0 +1,76 @@
+//! Looking up artifacts in more than one maven repository
+
+mod common;
+
+use common::{response, TestServer};
+use copper::downloader::Downloader;
+use copper::errors::MavenError;
+use copper::maven::MavenRepositories;
+use copper::util::MavenIdentifier;
+use reqwest::StatusCode;
+
+/// A server with a repository at `/broken` that fails every request, one at `/empty` that has
+/// nothing and one at `/maven` that has `org.example:example:1.0`
+async fn serve_repositories() -> TestServer {
+    TestServer::start(|request| {
+        let path = request.split_whitespace().nth(1).unwrap_or_default();
+
+        match path {
+            _ if path.starts_with("/broken/") => response("403 Forbidden", &[], b""),
+            "/maven/org/example/example/1.0/example-1.0.jar" => response("200 OK", &[], b"example"),
+            _ => response("404 Not Found", &[], b""),
+        }
+    })
+    .await
+}
+
+fn example() -> MavenIdentifier {
+    "org.example:example:1.0".parse().unwrap()
+}
+
+#[tokio::test]
+async fn skips_repositories_without_the_artifact() {
+    let server = serve_repositories().await;
+    let repositories = MavenRepositories::new([
+        format!("{}/empty", server.url),
+        format!("{}/maven", server.url),
+    ]);
+
+    let artifact = repositories
+        .resolve(&example(), &Downloader::with_defaults().unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(artifact.repository, format!("{}/maven", server.url));
+    assert_eq!(artifact.size, Some(7));
+}
+
+#[tokio::test]
+async fn reports_missing_artifacts_as_not_found() {
+    let server = serve_repositories().await;
+    let repositories = MavenRepositories::new([format!("{}/empty", server.url)]);
+
+    let result = repositories
+        .resolve(&example(), &Downloader::with_defaults().unwrap())
+        .await;
+
+    assert!(matches!(result, Err(MavenError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn returns_the_error_of_a_failed_repository() {
+    let server = serve_repositories().await;
+    let repositories = MavenRepositories::new([
+        format!("{}/broken", server.url),
+        format!("{}/empty", server.url),
+    ]);
+
+    let result = repositories
+        .resolve(&example(), &Downloader::with_defaults().unwrap())
+        .await;
+
+    assert!(matches!(
+        result,
+        Err(MavenError::RequestError(err)) if err.status() == Some(StatusCode::FORBIDDEN)
+    ));
+}
@@ -0,0 +1,233 @@
+//! Natives-only libraries and extracting natives
+
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use copper::assets::structs::version::{Library, Version};
+use copper::downloader::Downloader;
+use copper::natives::extract_natives;
+use serde_json::json;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+const NATIVES_PATH: &str = "org/lwjgl/lwjgl/lwjgl-platform/2.9.4-nightly-20150209/lwjgl-platform-2.9.4-nightly-20150209-natives.jar";
+
+/// LWJGL 2's `lwjgl-platform` as 1.12.2 lists it: natives only, without an `artifact`. Every OS
+/// points at the same jar so the tests work anywhere
+fn lwjgl_platform() -> serde_json::Value {
+    let natives = json!({
+        "path": NATIVES_PATH,
+        "sha1": "931074f46c795d2f7b30ed6395df5715cfd7675b",
+        "size": 578680,
+        "url": format!("https://libraries.minecraft.net/{NATIVES_PATH}"),
+    });
+
+    json!({
+        "downloads": {
+            "classifiers": {
+                "natives-linux": natives,
+                "natives-osx": natives,
+                "natives-windows": natives,
+            },
+        },
+        "extract": { "exclude": ["META-INF/"] },
+        "name": "org.lwjgl.lwjgl:lwjgl-platform:2.9.4-nightly-20150209",
+        "natives": {
+            "linux": "natives-linux",
+            "osx": "natives-osx",
+            "windows": "natives-windows",
+        },
+    })
+}
+
+fn version(libraries: Vec<serde_json::Value>) -> Version {
+    serde_json::from_value(json!({ "id": "1.12.2", "libraries": libraries })).unwrap()
+}
+
+fn write_jar(path: &Path, entries: &[(&str, &[u8])]) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let mut jar = ZipWriter::new(std::fs::File::create(path).unwrap());
+
+    for (name, data) in entries {
+        if name.ends_with('/') {
+            jar.add_directory(*name, FileOptions::default()).unwrap();
+        } else {
+            jar.start_file(*name, FileOptions::default()).unwrap();
+            jar.write_all(data).unwrap();
+        }
+    }
+
+    jar.finish().unwrap();
+}
+
+#[test]
+fn substitutes_the_arch_in_the_natives_classifier() {
+    let library: Library = serde_json::from_value(json!({
+        "name": "tv.twitch:twitch-platform:6.5",
+        "natives": {
+            "linux": "natives-linux-${arch}",
+            "osx": "natives-osx-${arch}",
+            "windows": "natives-windows-${arch}",
+        },
+    }))
+    .unwrap();
+
+    let arch = if cfg!(target_pointer_width = "64") {
+        "64"
+    } else {
+        "32"
+    };
+
+    let os = match std::env::consts::OS {
+        "windows" => "windows",
+        "macos" => "osx",
+        _ => "linux",
+    };
+
+    assert_eq!(
+        library.natives_classifier(),
+        Some(format!("natives-{os}-{arch}"))
+    );
+}
+
+#[test]
+fn has_no_natives_classifier_without_natives() {
+    let library: Library =
+        serde_json::from_value(json!({ "name": "com.mojang:patchy:1.1" })).unwrap();
+
+    assert_eq!(library.natives_classifier(), None);
+}
+
+#[tokio::test]
+async fn downloads_only_the_natives_of_natives_only_libraries() {
+    let version = version(vec![lwjgl_platform()]);
+    let libraries_directory = tempfile::tempdir().unwrap();
+
+    let jobs = version
+        .library_download_jobs(
+            libraries_directory.path().to_path_buf(),
+            &Downloader::with_defaults().unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let paths = jobs.iter().map(|job| job.path.clone()).collect::<Vec<_>>();
+    assert_eq!(paths, vec![libraries_directory.path().join(NATIVES_PATH)]);
+}
+
+#[tokio::test]
+async fn extracts_natives_without_excluded_entries() {
+    let libraries_directory = tempfile::tempdir().unwrap();
+    let version_directory = tempfile::tempdir().unwrap();
+    let natives_directory = version_directory.path().join("natives");
+
+    write_jar(
+        &libraries_directory.path().join(NATIVES_PATH),
+        &[
+            ("META-INF/", b""),
+            ("META-INF/MANIFEST.MF", b"Manifest-Version: 1.0\n"),
+            ("liblwjgl.so", b"lwjgl"),
+            ("nested/libopenal.so", b"openal"),
+        ],
+    );
+
+    let extracted = extract_natives(
+        &version(vec![lwjgl_platform()]),
+        libraries_directory.path(),
+        &natives_directory,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(extracted.jars, 1);
+    assert_eq!(extracted.directory, natives_directory);
+    assert_eq!(
+        std::fs::read(natives_directory.join("liblwjgl.so")).unwrap(),
+        b"lwjgl"
+    );
+    assert_eq!(
+        std::fs::read(natives_directory.join("nested/libopenal.so")).unwrap(),
+        b"openal"
+    );
+    assert!(!natives_directory.join("META-INF").exists());
+}
+
+#[tokio::test]
+async fn replaces_outdated_natives() {
+    let libraries_directory = tempfile::tempdir().unwrap();
+    let version_directory = tempfile::tempdir().unwrap();
+    let natives_directory = version_directory.path().join("natives");
+    let jar = libraries_directory.path().join(NATIVES_PATH);
+    let version = version(vec![lwjgl_platform()]);
+
+    write_jar(&jar, &[("liblwjgl.so", b"old")]);
+    extract_natives(&version, libraries_directory.path(), &natives_directory)
+        .await
+        .unwrap();
+
+    // the fingerprint only changes with the sha1, like it would for a different version
+    let mut newer = lwjgl_platform();
+    newer["downloads"]["classifiers"]["natives-linux"]["sha1"] = json!("newer");
+    newer["downloads"]["classifiers"]["natives-osx"]["sha1"] = json!("newer");
+    newer["downloads"]["classifiers"]["natives-windows"]["sha1"] = json!("newer");
+
+    write_jar(&jar, &[("liblwjgl.so", b"new")]);
+    let extracted = extract_natives(
+        &self::version(vec![newer]),
+        libraries_directory.path(),
+        &natives_directory,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(extracted.directory, natives_directory);
+    assert_eq!(
+        std::fs::read(natives_directory.join("liblwjgl.so")).unwrap(),
+        b"new"
+    );
+    assert_eq!(
+        std::fs::read_dir(version_directory.path()).unwrap().count(),
+        1,
+        "the old natives were left behind"
+    );
+}
+
+#[tokio::test]
+async fn removes_stale_folders_of_earlier_launches() {
+    let libraries_directory = tempfile::tempdir().unwrap();
+    let version_directory = tempfile::tempdir().unwrap();
+    let natives_directory = version_directory.path().join("natives");
+
+    write_jar(
+        &libraries_directory.path().join(NATIVES_PATH),
+        &[("liblwjgl.so", b"lwjgl")],
+    );
+
+    let old = version_directory.path().join("natives.1-1.old");
+    let crashed = version_directory.path().join("natives.2-2.tmp");
+    let extracting = version_directory.path().join("natives.3-3.tmp");
+    for directory in [&old, &crashed, &extracting] {
+        std::fs::create_dir(directory).unwrap();
+        std::fs::write(directory.join("liblwjgl.so"), b"lwjgl").unwrap();
+    }
+
+    let yesterday = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
+    std::fs::File::open(&crashed)
+        .unwrap()
+        .set_modified(yesterday)
+        .unwrap();
+
+    extract_natives(
+        &version(vec![lwjgl_platform()]),
+        libraries_directory.path(),
+        &natives_directory,
+    )
+    .await
+    .unwrap();
+
+    assert!(!old.exists());
+    assert!(!crashed.exists());
+    // another launch might still be extracting into it
+    assert!(extracting.exists());
+}